@@ -0,0 +1,31 @@
+//! A built-in strategy behind the engine protocol in
+//! `common::ai::protocol`, to try out `ai-client --engine` and to compare
+//! engines in other languages against:
+//!
+//! cargo run --bin ai-client -- --bots 3 --engine "target/debug/engine --strategy hard"
+
+use std::io;
+
+use clap::Parser;
+use common::{
+    ai::{protocol::serve, strategy_for},
+    Difficulty, SearchBudget,
+};
+
+#[derive(Parser, Debug)]
+#[command(about = "Play a built-in strategy over the engine protocol on stdin and stdout")]
+struct Args {
+    #[arg(long, default_value = "medium")]
+    strategy: Difficulty,
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+    let strategy = strategy_for(&args.strategy, &SearchBudget::default());
+    serve(
+        strategy.as_ref(),
+        &format!("blackball {}", args.strategy),
+        io::stdin().lock(),
+        io::stdout().lock(),
+    )
+}
@@ -0,0 +1,234 @@
+//! Plays computer games against each other without a server and reports how
+//! each strategy did.
+//!
+//! cargo run --release --bin simulate -- --seats 4 --strategies hard,medium --games 200 --seed 1
+//!
+//! Card values written by `tune` play as strategies too: `learned` bids with
+//! the built-in values, a path to a `.json` file with the values in it.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    time::Instant,
+};
+
+use clap::Parser;
+use common::{
+    ai::{play_game, strategy_for, CardValues, Learned, Strategy},
+    Difficulty, GameResult, RoundSummary, SearchBudget, SetupGameOptions,
+};
+use serde::Serialize;
+
+#[derive(Parser, Debug)]
+#[command(about = "Play computer games against each other and compare strategies")]
+struct Args {
+    /// Players at the table.
+    #[arg(long, default_value_t = 4)]
+    seats: usize,
+
+    /// Strategies to compare, handed out to the seats in turn. The seats
+    /// rotate every game so every strategy sits everywhere. One of easy,
+    /// medium, hard, expert, learned, or a card values `.json` file.
+    #[arg(long, value_delimiter = ',', default_value = "medium")]
+    strategies: Vec<String>,
+
+    #[arg(long, default_value_t = 100)]
+    games: usize,
+
+    /// Seed for the shuffles and the computers' random choices.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Rounds up to the most cards dealt, see `SetupGameOptions::rounds`.
+    #[arg(long, default_value_t = 9)]
+    rounds: usize,
+
+    /// Simulated rounds per decision for the expert strategy.
+    #[arg(long, default_value_t = SearchBudget::default().iterations)]
+    search_iterations: usize,
+
+    /// Write every finished game as a line of JSON to this file.
+    #[arg(long)]
+    jsonl: Option<PathBuf>,
+}
+
+/// One line of `--jsonl` output.
+#[derive(Serialize)]
+struct GameRecord<'a> {
+    game: usize,
+    seed: Option<u64>,
+    /// Strategy of every seat, `p1` first.
+    strategies: Vec<&'a str>,
+    round_summaries: &'a [RoundSummary],
+    game_result: &'a Option<GameResult>,
+}
+
+enum Contender {
+    Difficulty(Difficulty),
+    /// `Learned` with the values from `CardValues::default` or a file.
+    Values {
+        name: String,
+        values: CardValues,
+    },
+}
+
+impl Contender {
+    fn parse(arg: &str) -> Result<Contender, String> {
+        if arg == "learned" {
+            return Ok(Contender::Values {
+                name: arg.to_string(),
+                values: CardValues::default(),
+            });
+        }
+        if arg.ends_with(".json") {
+            let values = CardValues::load(arg).map_err(|e| format!("Could not load {arg}: {e}"))?;
+            return Ok(Contender::Values {
+                name: arg.to_string(),
+                values,
+            });
+        }
+        Ok(Contender::Difficulty(arg.parse()?))
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Contender::Difficulty(difficulty) => difficulty.to_string(),
+            Contender::Values { name, .. } => name.clone(),
+        }
+    }
+
+    fn strategy(&self, budget: &SearchBudget) -> Box<dyn Strategy + Send + Sync> {
+        match self {
+            Contender::Difficulty(difficulty) => strategy_for(difficulty, budget),
+            Contender::Values { values, .. } => Box::new(Learned {
+                values: values.clone(),
+            }),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Totals {
+    games: usize,
+    score: i32,
+    rounds: i32,
+    bids_made: i32,
+    blackballs: i32,
+    /// Sum of how many tricks every bid was off by.
+    bid_error: i32,
+}
+
+fn main() {
+    let args = Args::parse();
+    if args.seats < 2 || args.strategies.is_empty() {
+        eprintln!("Need at least 2 seats and 1 strategy");
+        std::process::exit(1);
+    }
+    let contenders: Vec<Contender> = args
+        .strategies
+        .iter()
+        .map(|arg| Contender::parse(arg))
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|e| {
+            eprintln!("{e}");
+            std::process::exit(1);
+        });
+    let names: Vec<String> = contenders.iter().map(Contender::name).collect();
+    if let Some(seed) = args.seed {
+        fastrand::seed(seed);
+    }
+    let budget = SearchBudget {
+        iterations: args.search_iterations,
+        time_ms: u64::MAX,
+    };
+    let mut jsonl = args.jsonl.as_ref().map(|path| {
+        BufWriter::new(File::create(path).expect("Could not create the JSON lines file"))
+    });
+
+    let mut totals: HashMap<String, Totals> = HashMap::new();
+    let started = Instant::now();
+    for game_num in 0..args.games {
+        let seats: Vec<usize> = (0..args.seats)
+            .map(|seat| (seat + game_num) % contenders.len())
+            .collect();
+        let strategies: Vec<Box<dyn Strategy + Send + Sync>> = seats
+            .iter()
+            .map(|contender| contenders[*contender].strategy(&budget))
+            .collect();
+        let game = play_game(
+            &strategies,
+            SetupGameOptions {
+                rounds: args.rounds,
+                ..SetupGameOptions::new()
+            },
+        );
+
+        for summary in game.round_summaries.iter() {
+            for result in summary.results.iter() {
+                let totals = totals
+                    .entry(seat_strategy(&names, &seats, &result.player))
+                    .or_default();
+                totals.rounds += 1;
+                totals.bid_error += (result.bid - result.tricks_won).abs();
+                if result.made_bid {
+                    totals.bids_made += 1;
+                } else {
+                    totals.blackballs += 1;
+                }
+            }
+        }
+        for (player, score) in game.score.iter() {
+            let totals = totals
+                .entry(seat_strategy(&names, &seats, player))
+                .or_default();
+            totals.games += 1;
+            totals.score += score;
+        }
+
+        if let Some(out) = jsonl.as_mut() {
+            let record = GameRecord {
+                game: game_num,
+                seed: args.seed,
+                strategies: seats.iter().map(|seat| names[*seat].as_str()).collect(),
+                round_summaries: &game.round_summaries,
+                game_result: &game.game_result,
+            };
+            serde_json::to_writer(&mut *out, &record).expect("Could not write game");
+            writeln!(out).expect("Could not write game");
+        }
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+
+    println!(
+        "{} games, {} seats, {:.1} games/sec",
+        args.games,
+        args.seats,
+        args.games as f64 / elapsed
+    );
+    println!(
+        "{:<10} {:>10} {:>10} {:>10} {:>12}",
+        "strategy", "avg score", "bids made", "blackball", "avg bid off"
+    );
+    let mut names: Vec<&String> = totals.keys().collect();
+    names.sort();
+    for name in names {
+        let t = &totals[name];
+        let rounds = t.rounds.max(1) as f64;
+        println!(
+            "{:<10} {:>10.1} {:>9.1}% {:>9.1}% {:>12.2}",
+            name,
+            t.score as f64 / t.games.max(1) as f64,
+            100.0 * t.bids_made as f64 / rounds,
+            100.0 * t.blackballs as f64 / rounds,
+            t.bid_error as f64 / rounds,
+        );
+    }
+}
+
+/// Strategy of the seat `play_game` named `player` (`p1`, `p2`, ...).
+fn seat_strategy(names: &[String], seats: &[usize], player: &str) -> String {
+    let seat: usize = player[1..].parse().expect("Not a seat name");
+    names[seats[seat - 1]].clone()
+}
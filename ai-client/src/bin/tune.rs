@@ -0,0 +1,90 @@
+//! Fits card values to self-play games and writes them to a file, to be
+//! compared with others in `simulate`:
+//!
+//! cargo run --release --bin tune -- --games 500 --seed 1 --out card_values.json
+//! cargo run --release --bin simulate -- --strategies learned,card_values.json
+//!
+//! The file is in the format of `common/card_value_matrix.json`, and values
+//! worth keeping can replace it. Computer players bid with it once rebuilt.
+
+use std::{path::PathBuf, time::Instant};
+
+use clap::Parser;
+use common::{
+    ai::{hand_samples, play_game, strategy_for, CardValues, HandSample, Strategy},
+    Difficulty, SearchBudget, SetupGameOptions,
+};
+
+#[derive(Parser, Debug)]
+#[command(about = "Fit card values to computer self-play")]
+struct Args {
+    /// Players at the table.
+    #[arg(long, default_value_t = 4)]
+    seats: usize,
+
+    /// Strategy every seat plays with.
+    #[arg(long, default_value = "hard")]
+    strategy: Difficulty,
+
+    #[arg(long, default_value_t = 500)]
+    games: usize,
+
+    #[arg(long)]
+    seed: Option<u64>,
+
+    #[arg(long, default_value_t = 9)]
+    rounds: usize,
+
+    /// How strongly values of rarely held cards are pulled towards 0.
+    #[arg(long, default_value_t = 1.0)]
+    ridge: f64,
+
+    #[arg(long, default_value = "card_values.json")]
+    out: PathBuf,
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Some(seed) = args.seed {
+        fastrand::seed(seed);
+    }
+
+    let started = Instant::now();
+    let mut samples = vec![];
+    for _ in 0..args.games {
+        let strategies: Vec<Box<dyn Strategy + Send + Sync>> = (0..args.seats)
+            .map(|_| strategy_for(&args.strategy, &SearchBudget::default()))
+            .collect();
+        let game = play_game(
+            &strategies,
+            SetupGameOptions {
+                rounds: args.rounds,
+                ..SetupGameOptions::new()
+            },
+        );
+        samples.extend(hand_samples(&game));
+    }
+    println!(
+        "{} hands from {} games in {:.1}s",
+        samples.len(),
+        args.games,
+        started.elapsed().as_secs_f64()
+    );
+
+    let values = CardValues::fit(&samples, args.ridge);
+    println!(
+        "avg tricks off: fitted {:.3}, default {:.3}",
+        avg_error(&values, &samples),
+        avg_error(&CardValues::default(), &samples)
+    );
+    values.save(&args.out).expect("Could not write card values");
+    println!("Wrote {}", args.out.display());
+}
+
+fn avg_error(values: &CardValues, samples: &[HandSample]) -> f32 {
+    let total: f32 = samples
+        .iter()
+        .map(|s| (values.expected_tricks(&s.hand, &s.trump) - s.tricks_won as f32).abs())
+        .sum();
+    total / samples.len().max(1) as f32
+}
@@ -0,0 +1,152 @@
+//! Plays through an engine, a program that speaks `common::ai::protocol` on
+//! its stdin and stdout.
+
+use std::{
+    io::{self, BufReader},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{
+    ai::{
+        protocol::{read_line, write_line, Reply, Request, VERSION},
+        PlayerObservation, Strategy,
+    },
+    Card, GameAction, LegalMoves,
+};
+use tracing::{info, warn};
+
+/// How long an engine gets to exit after `Request::Quit` before it is killed.
+const QUIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct Engine {
+    name: String,
+    child: Mutex<Child>,
+    io: Mutex<(ChildStdin, BufReader<ChildStdout>)>,
+}
+
+impl Engine {
+    /// Starts `command`, split on whitespace into the program and its
+    /// arguments, and checks that it speaks our version of the protocol.
+    pub fn spawn(command: &str) -> io::Result<Engine> {
+        let mut parts = command.split_whitespace();
+        let program = parts
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No engine command"))?;
+        let mut child = Command::new(program)
+            .args(parts)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("Engine has no stdin");
+        let stdout = BufReader::new(child.stdout.take().expect("Engine has no stdout"));
+        let mut engine = Engine {
+            name: program.to_string(),
+            child: Mutex::new(child),
+            io: Mutex::new((stdin, stdout)),
+        };
+
+        match engine.request(&Request::Hello { version: VERSION })? {
+            Reply::Hello { version, name } if version == VERSION => {
+                info!("Engine {name} started");
+                engine.name = name;
+                Ok(engine)
+            }
+            reply => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Expected hello for version {VERSION}, got {reply:?}"),
+            )),
+        }
+    }
+
+    /// Asks the engine to quit, and kills it if it hasn't after
+    /// `QUIT_TIMEOUT`. The waiting happens on a blocking thread.
+    pub async fn shutdown(self: Arc<Self>) {
+        let _ = tokio::task::spawn_blocking(move || self.quit()).await;
+    }
+
+    fn quit(&self) {
+        // an engine still working out a move doesn't get to finish it
+        if let Ok(mut io) = self.io.try_lock() {
+            let _ = write_line(&mut io.0, &Request::Quit);
+        }
+        let mut child = self.child.lock().expect("Engine lock poisoned");
+        let started = Instant::now();
+        while started.elapsed() < QUIT_TIMEOUT {
+            match child.try_wait() {
+                Ok(Some(_)) | Err(_) => return,
+                Ok(None) => thread::sleep(Duration::from_millis(20)),
+            }
+        }
+        warn!("{} didn't quit, killing it", self.name);
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    fn request(&self, request: &Request) -> io::Result<Reply> {
+        let mut io = self.io.lock().expect("Engine lock poisoned");
+        let (stdin, stdout) = &mut *io;
+        write_line(stdin, request)?;
+        read_line(stdout)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Engine exited"))
+    }
+
+    fn ask(&self, obs: &PlayerObservation, legal: LegalMoves) -> Option<GameAction> {
+        let reply = self.request(&Request::Move {
+            observation: Box::new(obs.clone()),
+            legal,
+        });
+        match reply {
+            Ok(Reply::Move { action }) => Some(action),
+            Ok(reply) => {
+                warn!("{} answered a move with {reply:?}", self.name);
+                None
+            }
+            Err(err) => {
+                warn!("{} failed: {err}", self.name);
+                None
+            }
+        }
+    }
+}
+
+/// A failing engine, or one that answers with an illegal move, makes the first
+/// legal move instead, so the table isn't left waiting.
+impl Strategy for Engine {
+    fn bid(&self, obs: &PlayerObservation) -> i32 {
+        let bids = obs.valid_bids();
+        match self.ask(obs, LegalMoves::Bids(bids.clone())) {
+            Some(GameAction::Bid(bid)) if bids.contains(&bid) => bid,
+            other => {
+                warn!("{} bid {other:?}, not one of {bids:?}", self.name);
+                bids[0]
+            }
+        }
+    }
+
+    fn play(&self, obs: &PlayerObservation) -> Card {
+        let cards = obs.legal_cards();
+        match self.ask(obs, LegalMoves::Cards(cards.clone())) {
+            Some(GameAction::PlayCard(card)) if cards.contains(&card) => card,
+            other => {
+                warn!("{} played {other:?}, not a legal card", self.name);
+                cards[0].clone()
+            }
+        }
+    }
+}
+
+/// Doesn't wait for anything, an engine that wasn't `shutdown` is killed.
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let Ok(child) = self.child.get_mut() else {
+            return;
+        };
+        if let Ok(None) = child.try_wait() {
+            warn!("{} is still running, killing it", self.name);
+            let _ = child.kill();
+        }
+    }
+}
@@ -1,341 +1,422 @@
-use std::{
-    env, io,
-    sync::{
-        mpsc::{self, Receiver},
-        Arc, Mutex,
-    },
-    thread::{self, sleep},
-    time::Duration,
-};
+use std::{sync::Arc, time::Duration};
 
+use api_types::CreateGameRequest;
 use chrono::Utc;
+use clap::Parser;
 use common::{
-    Actioner, Connect, GameAction, GameActionResponse, GameMessage, GameState, GameplayState,
-    PlayerDetails, SetupGameOptions,
+    ai::{strategy_for, PlayerObservation, Strategy},
+    Card, Difficulty, GameAction, GameActionResponse, GameMessage, GameState, GameplayState,
+    LegalMoves, PlayerDetails, SearchBudget,
 };
-
-use serde::{Deserialize, Serialize};
-use serde_json::{error, json};
-use tokio_tungstenite::tungstenite::{connect, Message};
-use tracing::info;
-use tracing_subscriber::{fmt::format::FmtSpan, util::SubscriberInitExt};
-
-struct AI {
+use futures_util::{SinkExt, StreamExt};
+use serde_json::json;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{debug, info, info_span, warn, Instrument, Level};
+use tracing_subscriber::util::SubscriberInitExt;
+
+mod engine;
+
+use engine::Engine;
+
+/// Longest wait between attempts to reconnect a bot.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Computer players that join games on a server.
+#[derive(Parser, Debug, Clone)]
+#[command(about = "Computer players that join BlackBall games on a server")]
+struct Args {
+    /// Address of the server, the websocket url is built from it.
+    #[arg(long, default_value = "http://0.0.0.0:8080")]
+    server: String,
+
+    /// Rooms to join, each created if it doesn't exist yet. Takes a comma
+    /// separated list.
+    #[arg(long, value_delimiter = ',', default_value = "a")]
+    lobby: Vec<String>,
+
+    /// Name at the table. With more than one bot in a room every bot gets a
+    /// number after it.
+    #[arg(long, default_value = "ai")]
     username: String,
-    lobby: String,
-    secret_key: String,
-}
 
-fn get_bid(gamestate: &GameState) -> GameAction {
-    let round_num = gamestate.curr_round;
-    let bid_total: i32 = gamestate.bids.values().map(|x| x.unwrap()).sum::<i32>();
-    let total_players = gamestate.players.len();
-    // let bid_order = gamestate.bid_order.clone();
+    /// easy, medium, hard or expert.
+    #[arg(long, default_value = "medium")]
+    strategy: Difficulty,
 
-    let mut my_bid = 0;
+    /// Play with an engine instead, started with this command. Every bot
+    /// starts its own, see `common::ai::protocol` for what they speak.
+    #[arg(long)]
+    engine: Option<String>,
 
-    if round_num == bid_total {
-        my_bid = 1;
-    }
+    /// How many bots join every room.
+    #[arg(long, default_value_t = 1)]
+    bots: usize,
+
+    /// Secret of a player the bot takes over, only with a single bot.
+    #[arg(long)]
+    secret: Option<String>,
 
-    return GameAction::Bid(my_bid);
+    /// Log more, repeat for even more.
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
 }
 
-impl AI {
-    fn create_action_from_user_input(&self, gamestate: &GameState) -> GameAction {
-        let mut user_input = String::new();
-        std::io::stdin().read_line(&mut user_input).unwrap();
-        let mut input_chars = user_input.trim().chars().collect::<Vec<char>>();
-
-        // let mut input_chars = user_input.chars().collect::<Vec<char>>();
-        info!("Chars: {:?}", input_chars);
-
-        let action = match input_chars[0] {
-            'b' => {
-                info!("Requesting Bid");
-                // _ = socket.send(Message::Text(
-                //     json!(GameMessage {
-                //         username: username.clone(),
-                //         message: GameEvent {
-                //             action: GameAction::Bid(input_chars[1].to_digit(10).unwrap() as i32),
-                //             origin: Actioner::Player(username.clone())
-                //         },
-                //         timestamp: Utc::now()
-                //     })
-                //     .to_string(),
-                // ));
-                GameAction::Bid(input_chars[1].to_digit(10).unwrap() as i32)
-            }
-            'p' => {
-                info!("Requesting to play a card");
-                let cards = GameState::decrypt_player_hand(
-                    gamestate
-                        .players
-                        .get(&self.username)
-                        .unwrap()
-                        .encrypted_hand
-                        .clone(),
-                    &self.secret_key,
-                );
-
-                let cardindex = input_chars[1].to_digit(10).unwrap() as usize;
-
-                if cardindex > cards.len() {
-                    info!("Chosen card location is not valid, try again");
-                }
-                let card = cards.get(cardindex).unwrap();
+/// One computer seat in one room.
+struct Bot {
+    username: String,
+    lobby: String,
+    /// Given by the server on the first connect, and used to get the same
+    /// seat back after a reconnect.
+    secret: Option<String>,
+    strategy: Box<dyn Strategy + Send + Sync>,
+    /// Where the game was when the bot last acted, and what the server had to
+    /// say then, so it doesn't send the same move again while the server
+    /// works through other players' moves.
+    acted_on: Option<(Progress, Vec<String>)>,
+}
 
-                info!("Playing card: {}", card);
-                GameAction::PlayCard(card.clone())
-            }
-            's' => {
-                info!("Requesting StartGame");
-                GameAction::StartGame(SetupGameOptions::new())
-            }
-            'c' => {
-                info!("Requesting CurrentState");
-                GameAction::CurrentState
-            }
-            _ => GameAction::CurrentState,
-        };
+/// State, round, bids made, tricks finished and cards on the table.
+type Progress = (GameplayState, i32, usize, usize, usize);
+
+fn progress(gamestate: &GameState) -> Progress {
+    (
+        gamestate.gameplay_state.clone(),
+        gamestate.curr_round,
+        gamestate.bids.values().filter(|bid| bid.is_some()).count(),
+        gamestate.tricks.len(),
+        gamestate.curr_played_cards.len(),
+    )
+}
 
-        return action;
+impl Bot {
+    fn message(&self, action: GameAction) -> GameMessage {
+        GameMessage {
+            username: self.username.clone(),
+            action,
+            timestamp: Utc::now(),
+            lobby: self.lobby.clone(),
+        }
     }
 
-    fn handle_event(&self, username: String, gamestate: GameState) -> Option<GameMessage> {
-        let action = self.decide_action(&gamestate);
+    fn my_hand(&self, gamestate: &GameState, secret: &String) -> Vec<Card> {
+        GameState::decrypt_player_hand(
+            gamestate.players[&self.username].encrypted_hand.clone(),
+            secret,
+        )
+    }
 
-        if let Some(chosen) = action {
-            return Some(GameMessage {
-                username: username,
-                timestamp: Utc::now(),
-                action: chosen,
-                lobby: gamestate.lobby_code.clone(),
-            });
+    /// Whether the bot already sent its move for this state. A move the server
+    /// turns down leaves the table as it was, but comes back with an error
+    /// while the bot is still on turn, so the bot tries again. Others being
+    /// told it isn't their turn doesn't count.
+    fn already_acted(&self, gamestate: &GameState) -> bool {
+        let Some((acted_progress, acted_status)) = &self.acted_on else {
+            return false;
+        };
+        if *acted_progress != progress(gamestate) {
+            return false;
         }
-        return None;
+        let others_told = format!("{}'s turn, not ", self.username);
+        let rejected = gamestate.curr_player_turn.as_ref() == Some(&self.username)
+            && gamestate.system_status != *acted_status
+            && gamestate
+                .system_status
+                .iter()
+                .any(|status| !status.starts_with(&others_told));
+        !rejected
     }
 
     fn decide_action(&self, gamestate: &GameState) -> Option<GameAction> {
-        let action = match &gamestate.gameplay_state {
-            common::GameplayState::Bid => get_bid(gamestate),
-            common::GameplayState::Pregame => return None,
-            common::GameplayState::PostHand(ps) => return None,
-            common::GameplayState::Play(ps) => {
-                // let player = gamestate.players.get(&self.username).unwrap();
-                let cards = GameState::decrypt_player_hand(
-                    gamestate
-                        .players
-                        .get(&self.username)
-                        .unwrap()
-                        .encrypted_hand
-                        .clone(),
-                    &self.secret_key,
-                );
-                info!("Cards: {:?}", cards);
-                GameAction::PlayCard(cards.get(0).unwrap().clone())
+        let me = gamestate.players.get(&self.username)?;
+        let secret = self.secret.as_ref()?;
+        let observe =
+            || PlayerObservation::new(gamestate, &self.username, &self.my_hand(gamestate, secret));
+        // the server only sends them to the player on turn
+        let legal_moves = GameState::decrypt_legal_moves(&me.encrypted_legal_moves, secret);
+
+        let action = match (&gamestate.gameplay_state, legal_moves) {
+            (GameplayState::Pregame, _) if !me.ready => GameAction::Ready(true),
+            (_, Some(LegalMoves::Bids(_))) => GameAction::Bid(self.strategy.bid(&observe())),
+            (_, Some(LegalMoves::Cards(cards))) => {
+                debug!("Legal cards: {cards:?}");
+                GameAction::PlayCard(self.strategy.play(&observe()))
+            }
+            (GameplayState::PostHand(_) | GameplayState::PostRound, _)
+                if self.strategy.ack(&observe()) =>
+            {
+                GameAction::Ack
             }
-            GameplayState::PostRound => GameAction::Deal,
-            GameplayState::End => GameAction::Ack,
+            _ => return None,
         };
         Some(action)
     }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    let secret = &args.get(1);
-
-    println!("Starting using secret {secret:?}");
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    if args.bots == 0 || (args.bots * args.lobby.len() > 1 && args.secret.is_some()) {
+        eprintln!("Need at least one bot, and a secret only works with a single bot");
+        std::process::exit(1);
+    }
 
+    let level = match args.verbose {
+        0 => Level::INFO,
+        1 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
     tracing_subscriber::fmt()
-        // .with_env_filter(
-        //     EnvFilter::from_default_env().add_directive("ai-client=debug".parse().unwrap()),
-        // )
-        .with_span_events(FmtSpan::FULL)
-        // .with_thread_names(true) // only says "tokio-runtime-worker"
-        .with_thread_ids(true)
+        .with_max_level(level)
         .finish()
         .init();
 
-    let debug_mode = Arc::new(Mutex::new(false));
-    let debug_mode_clone = Arc::clone(&debug_mode);
-    // let stdin_channel = spawn_stdin_channel();
-    // let (tx, rx) = mpsc::channel::<String>();
-    thread::spawn(move || loop {
-        let mut buffer = String::new();
-        io::stdin().read_line(&mut buffer).unwrap();
-
-        match buffer.trim() {
-            "d" => {
-                info!("Debug ON");
-                let mut inner = debug_mode_clone.lock().unwrap();
-                *inner = true;
-            }
-            "off" => {
-                info!("Debug OFF");
-                let mut inner = debug_mode_clone.lock().unwrap();
-                *inner = false;
-            }
-            _ => {}
-        }
-
-        sleep(Duration::from_secs(5));
-    });
-
-    let username = "ai".to_string();
-    let channel = "a".to_string();
-
-    let mut ai = AI {
-        username: username.clone(),
-        lobby: channel.clone(),
-        secret_key: if secret.is_some() {
-            secret.unwrap().clone()
-        } else {
-            String::new()
-        },
-    };
-
-    let (mut socket, response) = connect("ws://0.0.0.0:8080/ws").expect("Can't connect");
-
-    let message = socket.read().unwrap(); // read the ping message
-    info!("Server ping: {:?}, responding with pong", message);
-    let _ = socket.send(Message::Pong(vec![1, 2, 3]));
-    sleep(Duration::from_secs(2));
-
-    // let connect_secret =  if secret.is_some() {Some()} else{None};
-
-    let connect_action = if secret.is_some() {
-        GameMessage {
-            username: username.clone(),
-            action: GameAction::Connect(PlayerDetails {
+    let mut bots = vec![];
+    let mut engines = vec![];
+    for lobby in args.lobby.iter() {
+        for i in 0..args.bots {
+            let username = if args.bots == 1 {
+                args.username.clone()
+            } else {
+                format!("{}{}", args.username, i + 1)
+            };
+            let bot = Bot {
                 username: username.clone(),
-                client_secret: Some(secret.unwrap().clone()),
-                ip: None,
-                lobby: channel.clone(),
-            }),
-            timestamp: Utc::now(),
-            lobby: channel.clone(),
+                lobby: lobby.clone(),
+                secret: args.secret.clone(),
+                strategy: strategy(&args, &mut engines),
+                acted_on: None,
+            };
+            let span = info_span!("bot", name = %username, lobby = %lobby);
+            bots.push(tokio::spawn(
+                run_bot(args.server.clone(), bot).instrument(span),
+            ));
         }
-    } else {
-        GameMessage {
-            username: username.clone(),
-            action: GameAction::Connect(PlayerDetails {
-                username: username.clone(),
-                lobby: channel.clone(),
-                ip: None,
-                client_secret: None,
-            }),
-            timestamp: Utc::now(),
-            lobby: channel.clone(),
+    }
+    let play = async {
+        for bot in bots {
+            let _ = bot.await;
         }
     };
+    tokio::select! {
+        _ = play => {}
+        _ = tokio::signal::ctrl_c() => info!("Stopping"),
+    }
+    for engine in engines {
+        engine.shutdown().await;
+    }
+}
 
-    let res = socket.send(Message::Text(json!(connect_action).to_string()));
-
-    // info!("Connection results: {:?}", res);
-    let mut gamestate: Option<GameState> = None;
-    let mut num_error_status_messages = 0;
+/// The bot's strategy. Engines are also kept in `engines`, to be shut down
+/// when the bots stop.
+fn strategy(args: &Args, engines: &mut Vec<Arc<Engine>>) -> Box<dyn Strategy + Send + Sync> {
+    let Some(command) = &args.engine else {
+        return strategy_for(&args.strategy, &SearchBudget::default());
+    };
+    match Engine::spawn(command) {
+        Ok(engine) => {
+            let engine = Arc::new(engine);
+            engines.push(Arc::clone(&engine));
+            Box::new(engine)
+        }
+        Err(err) => {
+            eprintln!("Could not start the engine {command}: {err}");
+            std::process::exit(1);
+        }
+    }
+}
 
+/// Keeps the bot connected, reconnecting with a growing delay whenever the
+/// connection drops.
+async fn run_bot(server: String, mut bot: Bot) {
+    let mut delay = Duration::from_secs(1);
     loop {
-        // sleep(Duration::from_secs(1));
-
-        info!("Sleeping while waiting for messages");
-        // Read until we exhaust all messages, then try to
-        while let Ok(msg) = socket.read() {
-            // let msg = match message {
-            //     Ok(x) => x,
-            //     Err(err) => {
-            //         tracing::error!("Error reading message: {:?}", err);
-            //         break;
-            //     }
-            // };
-
-            let text = match msg {
-                Message::Text(text) => {
-                    tracing::info!("Message recieved: {}", text);
-                    // serde_json::from_str::<GameMessage>(&text).unwrap()
-                    text
-                }
-                _ => {
-                    tracing::error!("Unknown message type: {:?}", msg);
-                    break;
-                }
-            };
-
-            // GameEventResult { dest: User(PlayerDetails { username: "ai", ip: "", client_secret: "sky_669zn8s6ji4q" }), msg: Connect(Connect { username: "ai", channel: "a", secret: Some("sky_669zn8s6ji4q") }) }
+        match play(&server, &mut bot).await {
+            Ok(()) => {
+                // got as far as playing, start over with a short delay
+                delay = Duration::from_secs(1);
+                warn!("Connection closed, reconnecting in {delay:?}");
+            }
+            Err(err) => warn!("{err}, reconnecting in {delay:?}"),
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
 
-            match serde_json::from_str::<GameActionResponse>(&text).unwrap() {
-                common::GameActionResponse::Connect(con) => {
-                    info!("Got connect message: {con:?}");
-                    ai.secret_key = con.secret.unwrap_or(String::new());
-                }
-                common::GameActionResponse::GameState(gs) => {
-                    info!("Got game state: {gs:?}");
-                    gamestate = Some(gs);
-                }
-                common::GameActionResponse::Message(text) => {
-                    info!("Got message, not sure what to do with it: {text}");
+/// One connection to the server, until it drops. Returns an error if the bot
+/// never made it into the room.
+async fn play(server: &str, bot: &mut Bot) -> Result<(), String> {
+    ensure_room(server, &bot.lobby)
+        .await
+        .map_err(|e| format!("Could not find or create the room: {e}"))?;
+
+    let ws_url = format!(
+        "{}/rooms/{}/ws",
+        server.replacen("http", "ws", 1),
+        bot.lobby
+    );
+    let (socket, _) = connect_async(ws_url)
+        .await
+        .map_err(|e| format!("Could not connect: {e}"))?;
+    let (mut sender, mut receiver) = socket.split();
+
+    let connect = bot.message(GameAction::Connect(PlayerDetails {
+        username: bot.username.clone(),
+        client_secret: bot.secret.clone(),
+        ip: None,
+        lobby: bot.lobby.clone(),
+    }));
+    sender
+        .send(Message::Text(json!(connect).to_string()))
+        .await
+        .map_err(|e| format!("Could not join: {e}"))?;
+    info!("Connected");
+
+    // whatever was sent before may never have reached the server
+    bot.acted_on = None;
+    let mut joined = false;
+    while let Some(msg) = receiver.next().await {
+        let text = match msg {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => break,
+            Ok(_) => continue,
+            Err(err) => {
+                warn!("Read failed: {err}");
+                break;
+            }
+        };
+        let gamestate = match serde_json::from_str::<GameActionResponse>(&text) {
+            Ok(GameActionResponse::Connect(con)) => {
+                if con.username == bot.username && con.channel == bot.lobby {
+                    if con.secret.is_some() {
+                        bot.secret = con.secret;
+                    }
+                    joined = true;
+                    // the server doesn't send the table after a connect
+                    let msg = bot.message(GameAction::CurrentState);
+                    if let Err(err) = sender.send(Message::Text(json!(msg).to_string())).await {
+                        warn!("Send failed: {err}");
+                        break;
+                    }
                 }
+                continue;
             }
-
-            if gamestate.is_none() {
-                info!("No game state, waiting for game state");
+            Ok(GameActionResponse::GameState(gs)) => gs,
+            Ok(GameActionResponse::Message(text)) => {
+                debug!("Message: {text}");
                 continue;
             }
+            Ok(GameActionResponse::Hint(_)) => continue,
+            Err(err) => {
+                debug!("Could not read message: {err}");
+                continue;
+            }
+        };
 
-            let gs = gamestate.clone().unwrap();
-            let currplayer = gs.curr_player_turn.clone().unwrap_or("".to_string());
-            if currplayer.ne(&connect_action.username) {
-                // update gamestate with new values
-                info!("Not our turn, updated state and waiting.");
-                gamestate = Some(gs);
-            } else {
-                info!("Its our turn now, deciding on an action");
-                let mut action = ai.decide_action(&gs);
-
-                // our turn + errors increased = we caused an issue
-                if gs.system_status.len() > num_error_status_messages {
-                    info!("Error messages increased, setting debug mode ON.");
-                    *debug_mode.lock().unwrap() = true;
-                }
-
-                num_error_status_messages = gs.system_status.len();
+        // the server sends every room's updates to everyone
+        if gamestate.lobby_code != bot.lobby {
+            continue;
+        }
+        joined |= gamestate.players.contains_key(&bot.username);
+        if bot.already_acted(&gamestate) {
+            continue;
+        }
+        let action = tokio::task::block_in_place(|| bot.decide_action(&gamestate));
+        if let Some(action) = action {
+            debug!("Sending {action:?}");
+            bot.acted_on = Some((progress(&gamestate), gamestate.system_status.clone()));
+            let msg = bot.message(action);
+            if let Err(err) = sender.send(Message::Text(json!(msg).to_string())).await {
+                warn!("Send failed: {err}");
+                break;
+            }
+        }
+    }
 
-                if *debug_mode.lock().unwrap() == true {
-                    info!("AI chose an action, send it? (y, n) {:?}", action);
-                    let mut user_input = String::new();
-                    std::io::stdin().read_line(&mut user_input).unwrap();
-                    let inputaction = user_input.trim();
+    if joined {
+        Ok(())
+    } else {
+        Err("Disconnected before joining".to_string())
+    }
+}
 
-                    if inputaction.eq("n") {
-                        action = Some(ai.create_action_from_user_input(&gs));
-                        // continue;
-                    }
+/// Creates the room with `POST /rooms` unless the server already has it.
+async fn ensure_room(server: &str, lobby: &str) -> Result<(), reqwest::Error> {
+    let client = reqwest::Client::new();
+    let existing = client.get(format!("{server}/rooms/{lobby}")).send().await?;
+    if existing.status() != reqwest::StatusCode::NOT_FOUND {
+        existing.error_for_status()?;
+        return Ok(());
+    }
+    info!("Creating room {lobby}");
+    let created = client
+        .post(format!("{server}/rooms"))
+        .json(&CreateGameRequest {
+            lobby_code: lobby.to_string(),
+        })
+        .send()
+        .await?;
+    if created.status().is_success() {
+        return Ok(());
+    }
+    // another bot may have created it in the meantime
+    client
+        .get(format!("{server}/rooms/{lobby}"))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
 
-                    info!("debug is currently on");
-                } else {
-                    info!("debug is currently off");
-                }
+#[cfg(test)]
+mod tests {
+    use common::{PlayerRole, SetupGameOptions};
 
-                if let Some(todo) = action {
-                    _ = socket.send(Message::Text(
-                        json!(GameMessage {
-                            username: username.clone(),
-                            action: todo,
-                            timestamp: Utc::now(),
-                            lobby: gs.lobby_code.clone(),
-                        })
-                        .to_string(),
-                    ));
-                }
-            }
-        }
+    use super::*;
 
-        info!("Message had an error");
-        break;
+    fn send(game: &mut GameState, username: &str, action: GameAction) {
+        game.process_event(GameMessage {
+            username: username.to_string(),
+            action,
+            timestamp: Utc::now(),
+            lobby: game.lobby_code.clone(),
+        });
     }
 
-    info!("Exiting");
+    #[test]
+    fn test_bot_tries_again_after_a_rejected_move() {
+        let mut game = GameState::new("lobby".to_string());
+        game.add_player("p1".to_string(), PlayerRole::Leader, "ip".to_string());
+        game.add_player("p2".to_string(), PlayerRole::Player, "ip".to_string());
+        send(&mut game, "p1", GameAction::Ready(true));
+        send(&mut game, "p2", GameAction::Ready(true));
+        send(
+            &mut game,
+            "p1",
+            GameAction::StartGame(SetupGameOptions::new()),
+        );
+        assert_eq!(game.gameplay_state, GameplayState::Bid);
+
+        let username = game.curr_player_turn.clone().expect("Nobody on turn");
+        let mut bot = Bot {
+            username: username.clone(),
+            lobby: "lobby".to_string(),
+            secret: None,
+            strategy: strategy_for(&Difficulty::Easy, &SearchBudget::default()),
+            acted_on: None,
+        };
+        assert!(!bot.already_acted(&game));
+        bot.acted_on = Some((progress(&game), game.system_status.clone()));
+        assert!(bot.already_acted(&game));
+
+        // someone asking for the table changes nothing
+        let other = if username == "p1" { "p2" } else { "p1" };
+        send(&mut game, other, GameAction::CurrentState);
+        assert!(bot.already_acted(&game));
+
+        // the bid was turned down, the bot is still on turn
+        send(&mut game, &username, GameAction::Bid(-1));
+        assert!(!game.system_status.is_empty());
+        assert!(!bot.already_acted(&game));
+    }
 }
@@ -5,7 +5,7 @@ pub struct CreateGameResponse {
     pub lobby_code: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct CreateGameRequest {
     pub lobby_code: String,
 }
@@ -22,6 +22,8 @@ pub struct GetLobbyResponse {
 pub struct Lobby {
     pub name: String,
     pub players: Vec<String>,
+    /// Players who are ready to start.
+    pub ready: Vec<String>,
     pub max_players: usize,
     pub game_mode: String,
 }
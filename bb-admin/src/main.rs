@@ -7,8 +7,9 @@ use std::{collections::HashMap, path::Path};
 use api_types::{GetLobbiesResponse, GetLobbyResponse, Lobby};
 use chrono::Utc;
 use common::{
-    Card, Connect, Destination, GameAction, GameActionResponse, GameEventResult, GameMessage,
-    GameState, GameVisibility, GameplayState, PlayState, PlayerDetails, SetupGameOptions, Suit,
+    Card, Connect, Destination, Difficulty, FirstDealer, FirstLeadPolicy, GameAction, GameActionResponse,
+    GameEventResult, GameMessage, GameState, GameVisibility, GameplayState, Hint, LeavePolicy, LegalMoves, PlayState,
+    PlayerDetails, PlayerRole, RematchOptions, RematchSeating, SetupGameOptions, Suit, JOKER_VALUE,
 };
 use components::lobbylist;
 use components::state_provider::state_provider::StateProvider;
@@ -376,6 +377,7 @@ fn Home() -> Element {
                 visibility: GameVisibility::Public,
                 password: None,
                 computer_players: 0,
+                ..SetupGameOptions::new()
             }),
             timestamp: Utc::now(),
         });
@@ -715,6 +717,7 @@ fn GameRoom(room_code: String) -> Element {
     let mut user_config: Signal<UserConfig> = use_context::<Signal<UserConfig>>();
     let mut server_config: Signal<ServerConfig> = use_context::<Signal<ServerConfig>>();
     let mut gamestate = use_signal(|| GameState::new(room_code.clone()));
+    let mut hint = use_signal(|| None::<Hint>);
     let mut setupgameoptions = use_signal(|| SetupGameOptions {
         rounds: 4,
         deterministic: if app_props.read().is_prod() {
@@ -728,6 +731,7 @@ fn GameRoom(room_code: String) -> Element {
         visibility: GameVisibility::Public,
         password: None,
         computer_players: 0,
+        ..SetupGameOptions::new()
     });
 
     let mut ws_url = use_signal(|| {
@@ -742,6 +746,7 @@ fn GameRoom(room_code: String) -> Element {
         lobby: Lobby {
             name: room_code.clone(),
             players: vec![],
+            ready: vec![],
             max_players: 4,
             game_mode: "Standard".to_string(),
         },
@@ -801,6 +806,7 @@ fn GameRoom(room_code: String) -> Element {
                         lobby: Lobby {
                             name: get_details_room_code.clone(),
                             players: vec![],
+                            ready: vec![],
                             max_players: 4,
                             game_mode: "Standard".to_string(),
                         },
@@ -844,11 +850,21 @@ fn GameRoom(room_code: String) -> Element {
                                 }
                                 common::GameActionResponse::GameState(gs) => {
                                     info!("Got game state");
+                                    // a hint is only good for the turn it was asked on
+                                    if gs.curr_player_turn != gamestate.read().curr_player_turn
+                                        || gs.gameplay_state != gamestate.read().gameplay_state
+                                    {
+                                        hint.set(None);
+                                    }
                                     gamestate.set(gs);
                                 }
                                 common::GameActionResponse::Message(text) => {
                                     info!("Got message");
                                 }
+                                common::GameActionResponse::Hint(h) => {
+                                    info!("Got hint");
+                                    hint.set(Some(h));
+                                }
                             }
                         }
                         Err(err) => {
@@ -939,8 +955,15 @@ fn GameRoom(room_code: String) -> Element {
                                                 .players
                                                 .iter()
                                                 .enumerate()
-                                                .map(|(i, player)| rsx! {
-                                                    div { "{i}: {player}" }
+                                                .map(|(i, player)| {
+                                                    let ready = if get_lobby_response.read().lobby.ready.contains(player) {
+                                                        " (ready)"
+                                                    } else {
+                                                        ""
+                                                    };
+                                                    rsx! {
+                                                        div { "{i}: {player}{ready}" }
+                                                    }
                                                 })
                                         }
                                     }
@@ -1068,6 +1091,37 @@ fn GameRoom(room_code: String) -> Element {
                                             }
                                         }
                                     }
+                                    for i in 0..setupgameoptions.read().computer_players {
+                                        div { class: "flex flex-row items-center justify-center space-x-2",
+                                            label { class: "text-sm md:text-base", "cpu_{i}" }
+                                            select {
+                                                class: "border border-black rounded-md p-1 text-sm",
+                                                onchange: move |evt| {
+                                                    let difficulty = match evt.value().as_str() {
+                                                        "Easy" => Difficulty::Easy,
+                                                        "Hard" => Difficulty::Hard,
+                                                        "Expert" => Difficulty::Expert,
+                                                        _ => Difficulty::Medium,
+                                                    };
+                                                    let mut options = setupgameoptions.write();
+                                                    if options.computer_difficulty.len() <= i {
+                                                        options.computer_difficulty.resize(i + 1, Difficulty::default());
+                                                    }
+                                                    options.computer_difficulty[i] = difficulty;
+                                                },
+                                                for value in ["Easy", "Medium", "Hard", "Expert"] {
+                                                    option {
+                                                        value: "{value}",
+                                                        selected: format!(
+                                                            "{:?}",
+                                                            setupgameoptions.read().computer_difficulty.get(i).cloned().unwrap_or_default(),
+                                                        ) == value,
+                                                        "{value}"
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
                                     if app_props.read().is_debug_mode() {
                                         div { class: "flex flex-row items-center justify-center space-x-4",
                                             label { class: "text-sm md:text-base", "start round" }
@@ -1084,6 +1138,180 @@ fn GameRoom(room_code: String) -> Element {
                                             }
                                         }
                                     }
+                                    div { class: "flex flex-row items-center justify-center space-x-2",
+                                        label { class: "text-sm md:text-base", "Decks" }
+                                        input {
+                                            class: "{styles::INPUT_FIELD} w-16 md:w-20",
+                                            r#type: "number",
+                                            min: "1",
+                                            value: "{setupgameoptions.read().decks}",
+                                            onchange: move |evt| {
+                                                setupgameoptions.write().decks = evt.value().parse::<usize>().unwrap_or(1).max(1);
+                                            },
+                                        }
+                                        label { class: "text-sm md:text-base", "Jokers" }
+                                        input {
+                                            class: "{styles::INPUT_FIELD} w-16 md:w-20",
+                                            r#type: "number",
+                                            min: "0",
+                                            value: "{setupgameoptions.read().jokers}",
+                                            onchange: move |evt| {
+                                                setupgameoptions.write().jokers = evt.value().parse::<usize>().unwrap_or(0);
+                                            },
+                                        }
+                                    }
+                                    div { class: "flex flex-row flex-wrap items-center justify-center gap-2",
+                                        label { class: "text-sm md:text-base", "Seats" }
+                                        {
+                                            (1..=setupgameoptions.read().max_players)
+                                                .map(|seat| {
+                                                    let taken_by = gamestate()
+                                                        .players
+                                                        .values()
+                                                        .find(|player| player.seat == Some(seat))
+                                                        .map(|player| player.id.clone());
+                                                    let text = match taken_by {
+                                                        Some(player) => format!("{seat}: {player}"),
+                                                        None => format!("{seat}: empty"),
+                                                    };
+                                                    rsx! {
+                                                        button {
+                                                            class: "border border-black rounded-md p-1 text-sm",
+                                                            onclick: move |_| {
+                                                                ws_send
+                                                                    .send(InnerMessage::GameMessage {
+                                                                        msg: GameMessage {
+                                                                            username: user_config.read().username.clone(),
+                                                                            action: GameAction::ClaimSeat(seat),
+                                                                            lobby: user_config.read().lobby_code.clone(),
+                                                                            timestamp: Utc::now(),
+                                                                        },
+                                                                    });
+                                                            },
+                                                            "{text}"
+                                                        }
+                                                    }
+                                                })
+                                        }
+                                    }
+                                    div { class: "flex flex-row items-center justify-center space-x-2",
+                                        label { class: "text-sm md:text-base", "Random seats" }
+                                        input {
+                                            r#type: "checkbox",
+                                            checked: setupgameoptions.read().random_seats,
+                                            onchange: move |evt| {
+                                                setupgameoptions.write().random_seats = evt.checked();
+                                            },
+                                        }
+                                    }
+                                    div { class: "flex flex-row items-center justify-center space-x-2",
+                                        label { class: "text-sm md:text-base", "Hints" }
+                                        input {
+                                            r#type: "checkbox",
+                                            checked: setupgameoptions.read().hints,
+                                            onchange: move |evt| {
+                                                setupgameoptions.write().hints = evt.checked();
+                                            },
+                                        }
+                                    }
+                                    div { class: "flex flex-row items-center justify-center space-x-2",
+                                        label { class: "text-sm md:text-base", "First dealer" }
+                                        select {
+                                            class: "border border-black rounded-md p-1 text-sm",
+                                            onchange: move |evt| {
+                                                setupgameoptions.write().first_dealer = match evt.value().as_str() {
+                                                    "Random" => FirstDealer::Random,
+                                                    "CutForDeal" => FirstDealer::CutForDeal,
+                                                    _ => FirstDealer::SeatOne,
+                                                };
+                                            },
+                                            option {
+                                                value: "SeatOne",
+                                                selected: setupgameoptions.read().first_dealer == FirstDealer::SeatOne,
+                                                "Seat 1"
+                                            }
+                                            option {
+                                                value: "Random",
+                                                selected: setupgameoptions.read().first_dealer == FirstDealer::Random,
+                                                "Random"
+                                            }
+                                            option {
+                                                value: "CutForDeal",
+                                                selected: setupgameoptions.read().first_dealer == FirstDealer::CutForDeal,
+                                                "Cut for deal"
+                                            }
+                                        }
+                                    }
+                                    div { class: "flex flex-row items-center justify-center space-x-2",
+                                        label { class: "text-sm md:text-base", "Computer speed" }
+                                        select {
+                                            class: "border border-black rounded-md p-1 text-sm",
+                                            onchange: move |evt| {
+                                                setupgameoptions.write().computer_think_ms = evt.value().parse::<u64>().unwrap_or(1000);
+                                            },
+                                            option {
+                                                value: "300",
+                                                selected: setupgameoptions.read().computer_think_ms == 300,
+                                                "Fast"
+                                            }
+                                            option {
+                                                value: "1000",
+                                                selected: setupgameoptions.read().computer_think_ms == 1000,
+                                                "Normal"
+                                            }
+                                            option {
+                                                value: "3000",
+                                                selected: setupgameoptions.read().computer_think_ms == 3000,
+                                                "Slow"
+                                            }
+                                        }
+                                    }
+                                    div { class: "flex flex-row items-center justify-center space-x-2",
+                                        label { class: "text-sm md:text-base", "If a player leaves" }
+                                        select {
+                                            class: "border border-black rounded-md p-1 text-sm",
+                                            onchange: move |evt| {
+                                                setupgameoptions.write().on_leave = if evt.value() == "Forfeit" {
+                                                    LeavePolicy::Forfeit
+                                                } else {
+                                                    LeavePolicy::ReplaceWithComputer
+                                                };
+                                            },
+                                            option {
+                                                value: "ReplaceWithComputer",
+                                                selected: setupgameoptions.read().on_leave == LeavePolicy::ReplaceWithComputer,
+                                                "A computer takes over"
+                                            }
+                                            option {
+                                                value: "Forfeit",
+                                                selected: setupgameoptions.read().on_leave == LeavePolicy::Forfeit,
+                                                "They forfeit"
+                                            }
+                                        }
+                                    }
+                                    div { class: "flex flex-row items-center justify-center space-x-2",
+                                        label { class: "text-sm md:text-base", "First lead" }
+                                        select {
+                                            class: "border border-black rounded-md p-1 text-sm",
+                                            onchange: move |evt| {
+                                                setupgameoptions.write().first_lead = if evt.value() == "LeftOfDealer" {
+                                                    FirstLeadPolicy::LeftOfDealer
+                                                } else {
+                                                    FirstLeadPolicy::HighestBidder
+                                                };
+                                            },
+                                            option {
+                                                value: "HighestBidder",
+                                                selected: setupgameoptions.read().first_lead == FirstLeadPolicy::HighestBidder,
+                                                "Highest bidder"
+                                            }
+                                            option {
+                                                value: "LeftOfDealer",
+                                                selected: setupgameoptions.read().first_lead == FirstLeadPolicy::LeftOfDealer,
+                                                "Left of dealer"
+                                            }
+                                        }
+                                    }
                                     div { class: "flex flex-row items-center justify-center space-x-4",
                                         span { class: "text-sm md:text-base", "Public" }
                                         label { class: "relative flex items-center cursor-pointer",
@@ -1190,6 +1418,51 @@ fn GameRoom(room_code: String) -> Element {
                                     },
                                     "Start game"
                                 }
+                                {
+                                    let me = gamestate().players.get(&user_config.read().username).cloned();
+                                    let is_ready = me.as_ref().is_some_and(|player| player.ready);
+                                    let is_leader = me.as_ref().is_some_and(|player| player.role == PlayerRole::Leader);
+                                    rsx! {
+                                        if me.is_some() {
+                                            button {
+                                                class: "{styles::STANDARD_BUTTON} text-white",
+                                                onclick: move |evt| {
+                                                    ws_send
+                                                        .send(InnerMessage::GameMessage {
+                                                            msg: GameMessage {
+                                                                username: user_config.read().username.clone(),
+                                                                action: GameAction::Ready(!is_ready),
+                                                                lobby: user_config.read().lobby_code.clone(),
+                                                                timestamp: Utc::now(),
+                                                            },
+                                                        });
+                                                },
+                                                if is_ready {
+                                                    "Not ready"
+                                                } else {
+                                                    "Ready"
+                                                }
+                                            }
+                                        }
+                                        if is_leader && !gamestate().players_not_ready().is_empty() {
+                                            button {
+                                                class: "{styles::STANDARD_BUTTON} text-white",
+                                                onclick: move |evt| {
+                                                    ws_send
+                                                        .send(InnerMessage::GameMessage {
+                                                            msg: GameMessage {
+                                                                username: user_config.read().username.clone(),
+                                                                action: GameAction::ForceStart(setupgameoptions()),
+                                                                lobby: user_config.read().lobby_code.clone(),
+                                                                timestamp: Utc::now(),
+                                                            },
+                                                        });
+                                                },
+                                                "Start without waiting"
+                                            }
+                                        }
+                                    }
+                                }
                                 div { class: "flex flex-col w-full",
                                     {
                                         if gamestate().system_status.len() > 0 {
@@ -1214,7 +1487,7 @@ fn GameRoom(room_code: String) -> Element {
                     }
                 } else {
                     rsx! {
-                        GameStateComponent { gamestate, ws_send: ws_send_signal }
+                        GameStateComponent { gamestate, hint, ws_send: ws_send_signal }
                     }
                 }
             }
@@ -1233,11 +1506,37 @@ pub const SUIT_DIAMOND: Asset = asset!("./public/suits/diamond.png");
 pub const SUIT_SPADE: Asset = asset!("./public/suits/spade.png");
 pub const SUIT_NOTRUMP: Asset = asset!("./public/suits/notrump.png");
 
+fn card_value_text(value: i32) -> String {
+    match value {
+        11 => "J".to_string(),
+        12 => "Q".to_string(),
+        13 => "K".to_string(),
+        14 => "A".to_string(),
+        JOKER_VALUE => "JK".to_string(),
+        val => val.to_string().clone(),
+    }
+}
+
+/// "Bid 2: 3 trump cards, 2 high off-suit, about 2.4 tricks"
+fn hint_text(hint: &Hint) -> String {
+    let suggestion = match &hint.action {
+        GameAction::Bid(bid) => format!("Bid {bid}"),
+        GameAction::PlayCard(card) if card.suit == Suit::Joker => "Play a joker".to_string(),
+        GameAction::PlayCard(card) => {
+            format!("Play the {} of {:?}s", card_value_text(card.value), card.suit)
+        }
+        _ => "Hint".to_string(),
+    };
+    format!("{suggestion}: {}", hint.explanation)
+}
+
 #[component]
 fn CardComponent(
     card: Card,
     onclick: EventHandler<Card>,
     is_winning: bool,
+    /// Greyed out and not clickable when false.
+    is_legal: bool,
     show_player: bool,
     show_order: bool,
     order: usize,
@@ -1247,22 +1546,17 @@ fn CardComponent(
         Suit::Diamond => SUIT_DIAMOND,
         Suit::Club => SUIT_CLUB,
         Suit::Spade => SUIT_SPADE,
-        Suit::NoTrump => SUIT_NOTRUMP,
+        Suit::NoTrump | Suit::Joker => SUIT_NOTRUMP,
     };
 
-    let textvalue = match card.value {
-        11 => "J".to_string(),
-        12 => "Q".to_string(),
-        13 => "K".to_string(),
-        14 => "A".to_string(),
-        val => val.to_string().clone(),
-    };
+    let textvalue = card_value_text(card.value);
 
     let suit_svg = get_trump_svg(&card.suit);
 
     rsx!(
         button {
-            class: "grid place-items-center relative",
+            class: if is_legal { "grid place-items-center relative" } else { "grid place-items-center relative opacity-50" },
+            disabled: !is_legal,
             onclick: move |evt| {
                 onclick(card.clone());
             },
@@ -1320,6 +1614,23 @@ fn CardComponent(
     )
 }
 
+#[component]
+fn AckStatusComponent(gamestate: Signal<GameState>) -> Element {
+    let waiting = gamestate().waiting_on_acks().join(", ");
+    let seconds_left = gamestate()
+        .ack_deadline
+        .map(|deadline| deadline.signed_duration_since(Utc::now()).num_seconds().max(0));
+
+    rsx! {
+        if !waiting.is_empty() {
+            p { class: "text-xs", "Waiting on {waiting}" }
+        }
+        if let Some(seconds) = seconds_left {
+            p { class: "text-xs", "Moving on in {seconds}s" }
+        }
+    }
+}
+
 #[component]
 fn GameStatusInfoComponent(gamestate: Signal<GameState>, visible: bool) -> Element {
     let mut app_props = use_context::<Signal<AppProps>>();
@@ -1341,6 +1652,7 @@ fn GameStatusInfoComponent(gamestate: Signal<GameState>, visible: bool) -> Eleme
                         span { class: "text-yellow-300", "{round_winner}" }
                         "!"
                     }
+                    AckStatusComponent { gamestate }
                 }
             }
         }
@@ -1350,15 +1662,29 @@ fn GameStatusInfoComponent(gamestate: Signal<GameState>, visible: bool) -> Eleme
                     p { class: "text-base sm:text-lg font-semibold",
                         "{gamestate().curr_player_turn.clone().unwrap()}'s turn to bid"
                     }
+                    if gamestate().computer_move_at.is_some() {
+                        p { class: "text-xs", "Thinking..." }
+                    }
                 }
             }
         }
         GameplayState::Play(ps) => {
+            let lead_reason = if ps.hand_num == 1 && gamestate().curr_played_cards.is_empty() {
+                gamestate().round_lead.map(|lead| lead.reason)
+            } else {
+                None
+            };
             rsx! {
                 div { class: "{styles::ROUND_DETAILS_TAILWIND}",
                     p { class: "text-base sm:text-lg font-semibold",
                         "{gamestate().curr_player_turn.clone().unwrap()}'s turn to play a card"
                     }
+                    if gamestate().computer_move_at.is_some() {
+                        p { class: "text-xs", "Thinking..." }
+                    }
+                    if let Some(reason) = lead_reason {
+                        p { class: "text-xs", "Leads because {reason}" }
+                    }
                 }
             }
         }
@@ -1373,27 +1699,27 @@ fn GameStatusInfoComponent(gamestate: Signal<GameState>, visible: bool) -> Eleme
             rsx! {
                 div { class: "{styles::ROUND_DETAILS_TAILWIND}",
                     p { class: "text-base sm:text-lg font-semibold", "Round over" }
+                    AckStatusComponent { gamestate }
                     ul { class: "text-left text-sm w-full justify-center",
                         {
                             gamestate()
-                                .players
-                                .iter()
-                                .map(|(player, client)| {
-                                    let wins = gamestate().wins.get(player).unwrap_or(&0).clone();
-                                    let bid = gamestate()
-                                        .bids
-                                        .get(player)
-                                        .unwrap_or(&Some(0))
-                                        .clone()
-                                        .unwrap();
+                                .round_summary
+                                .map(|summary| summary.results)
+                                .unwrap_or_default()
+                                .into_iter()
+                                .map(|result| {
                                     let win_message = format!(
-                                        "got {wins}/{bid}{}",
-                                        if wins == bid { "" } else { " got BLACKBALL" },
+                                        "got {}/{}{} (+{}, total {})",
+                                        result.tricks_won,
+                                        result.bid,
+                                        if result.made_bid { "" } else { " got BLACKBALL" },
+                                        result.points,
+                                        result.total_score,
                                     );
                                     rsx! {
                                         li { class: "flex flex-col items-center justify-center",
                                             div { class: "flex justify-between gap-2",
-                                                span { "{player}" }
+                                                span { "{result.player}" }
                                                 span { "{win_message}" }
                                             }
                                         }
@@ -1405,13 +1731,16 @@ fn GameStatusInfoComponent(gamestate: Signal<GameState>, visible: bool) -> Eleme
             }
         }
         GameplayState::End => {
-            let gamewinner = gamestate()
-                .score
+            let standings = gamestate()
+                .game_result
+                .map(|result| result.standings)
+                .unwrap_or_default();
+            let gamewinner = standings
                 .iter()
-                .max_by_key(|(_, v)| *v)
-                .unwrap()
-                .0
-                .clone();
+                .filter(|standing| standing.rank == 1)
+                .map(|standing| standing.player.clone())
+                .collect::<Vec<String>>()
+                .join(", ");
             rsx! {
                 div { class: "{styles::ROUND_DETAILS_TAILWIND}",
                     p { class: "text-base sm:text-lg font-semibold",
@@ -1421,12 +1750,17 @@ fn GameStatusInfoComponent(gamestate: Signal<GameState>, visible: bool) -> Eleme
                     }
                     ul { class: "text-sm ",
                         {
-                            gamestate()
-                                .players
-                                .iter()
-                                .map(|(player, client)| {
-                                    let score = gamestate().score.get(player).unwrap_or(&0).clone();
-                                    let text = format!("{player}: {score}");
+                            standings
+                                .into_iter()
+                                .map(|standing| {
+                                    let text = format!(
+                                        "{}. {}: {} ({} blackballs){}",
+                                        standing.rank,
+                                        standing.player,
+                                        standing.score,
+                                        standing.stats.blackballs,
+                                        if standing.forfeited { " - forfeited" } else { "" },
+                                    );
                                     rsx! {
                                         li { "{text}" }
                                     }
@@ -1477,6 +1811,7 @@ fn TransitionComponent(gamestate: Signal<GameState>, visible: bool) -> Element {
 #[component]
 fn GameStateComponent(
     gamestate: Signal<GameState>,
+    hint: Signal<Option<Hint>>,
     ws_send: Signal<Coroutine<InnerMessage>>,
 ) -> Element {
     let mut app_props = use_context::<Signal<AppProps>>();
@@ -1509,6 +1844,26 @@ fn GameStateComponent(
         None
     };
 
+    // only sent to us on our turn
+    let legal_moves = gamestate
+        .read()
+        .players
+        .get(&user_config.read().username)
+        .and_then(|player| {
+            GameState::decrypt_legal_moves(
+                &player.encrypted_legal_moves,
+                &user_config.read().client_secret,
+            )
+        });
+    let legal_bids = match &legal_moves {
+        Some(LegalMoves::Bids(bids)) => bids.clone(),
+        _ => vec![],
+    };
+    let legal_cards = match &legal_moves {
+        Some(LegalMoves::Cards(cards)) => Some(cards.clone()),
+        _ => None,
+    };
+
     // let testvec: Vec<Card> = vec![];
     // testvec.sort_by(|a, b| a.id.cmp(&b.id));
 
@@ -1546,6 +1901,130 @@ fn GameStateComponent(
                                     "{gamestate().curr_round}/{gamestate().max_rounds}"
                                 }
                             }
+                            button {
+                                class: "text-xs border border-black rounded-md px-1",
+                                onclick: move |_| {
+                                    ws_send()
+                                        .send(InnerMessage::GameMessage {
+                                            msg: GameMessage {
+                                                username: user_config.read().username.clone(),
+                                                action: GameAction::Leave,
+                                                lobby: user_config.read().lobby_code.clone(),
+                                                timestamp: Utc::now(),
+                                            },
+                                        });
+                                },
+                                "Leave"
+                            }
+                            button {
+                                class: "text-xs border border-black rounded-md px-1",
+                                onclick: move |_| {
+                                    ws_send()
+                                        .send(InnerMessage::GameMessage {
+                                            msg: GameMessage {
+                                                username: user_config.read().username.clone(),
+                                                action: GameAction::RequestUndo,
+                                                lobby: user_config.read().lobby_code.clone(),
+                                                timestamp: Utc::now(),
+                                            },
+                                        });
+                                },
+                                "Undo"
+                            }
+                            if gamestate()
+                                .players
+                                .get(&user_config.read().username)
+                                .is_some_and(|player| player.role == PlayerRole::Leader)
+                            {
+                                button {
+                                    class: "text-xs border border-black rounded-md px-1",
+                                    onclick: move |_| {
+                                        let action = if gamestate().paused {
+                                            GameAction::Resume
+                                        } else {
+                                            GameAction::Pause
+                                        };
+                                        ws_send()
+                                            .send(InnerMessage::GameMessage {
+                                                msg: GameMessage {
+                                                    username: user_config.read().username.clone(),
+                                                    action,
+                                                    lobby: user_config.read().lobby_code.clone(),
+                                                    timestamp: Utc::now(),
+                                                },
+                                            });
+                                    },
+                                    if gamestate().paused {
+                                        "Resume"
+                                    } else {
+                                        "Pause"
+                                    }
+                                }
+                            }
+                        }
+                        if gamestate().paused {
+                            div { class: "w-full rounded-md bg-yellow-300 text-black text-sm font-bold",
+                                "Game paused by the leader"
+                            }
+                        }
+                        if gamestate().get_setup_game_options().hints
+                            && gamestate().curr_player_turn == Some(user_config.read().username.clone())
+                            && matches!(gamestate().gameplay_state, GameplayState::Bid | GameplayState::Play(_))
+                        {
+                            div { class: "flex flex-row w-full gap-2 items-center justify-center rounded-md bg-blue-100 text-black text-sm",
+                                match hint() {
+                                    Some(hint) => rsx! {
+                                        span { "{hint_text(&hint)}" }
+                                    },
+                                    None => rsx! {
+                                        button {
+                                            class: "text-xs border border-black rounded-md px-1",
+                                            onclick: move |_| {
+                                                ws_send()
+                                                    .send(InnerMessage::GameMessage {
+                                                        msg: GameMessage {
+                                                            username: user_config.read().username.clone(),
+                                                            action: GameAction::RequestHint,
+                                                            lobby: user_config.read().lobby_code.clone(),
+                                                            timestamp: Utc::now(),
+                                                        },
+                                                    });
+                                            },
+                                            "Hint"
+                                        }
+                                    },
+                                }
+                            }
+                        }
+                        if let Some(request) = gamestate().undo_request {
+                            div { class: "flex flex-row w-full gap-2 items-center justify-center rounded-md bg-yellow-100 text-black text-sm",
+                                span { "{request.requested_by} wants to undo their last move" }
+                                if !request.approved.contains(&user_config.read().username) {
+                                    {
+                                        [(GameAction::ApproveUndo, "Approve"), (GameAction::DeclineUndo, "Decline")]
+                                            .into_iter()
+                                            .map(|(action, text)| {
+                                                rsx! {
+                                                    button {
+                                                        class: "text-xs border border-black rounded-md px-1",
+                                                        onclick: move |_| {
+                                                            ws_send()
+                                                                .send(InnerMessage::GameMessage {
+                                                                    msg: GameMessage {
+                                                                        username: user_config.read().username.clone(),
+                                                                        action: action.clone(),
+                                                                        lobby: user_config.read().lobby_code.clone(),
+                                                                        timestamp: Utc::now(),
+                                                                    },
+                                                                });
+                                                        },
+                                                        "{text}"
+                                                    }
+                                                }
+                                            })
+                                    }
+                                }
+                            }
                         }
                     }
                     div { class: "bg-bg-color rounded-lg  overflow-auto w-full",
@@ -1637,6 +2116,7 @@ fn GameStateComponent(
                                     card: card.clone(),
                                     is_winning: gamestate.read().curr_winning_card.is_some()
                                         && gamestate.read().curr_winning_card.clone().unwrap() == card.clone(),
+                                    is_legal: true,
                                     show_player: true,
                                     show_order: true,
                                     order: i,
@@ -1697,6 +2177,9 @@ fn GameStateComponent(
                                                         card: card.clone(),
                                                         is_winning: gamestate.read().curr_winning_card.is_some()
                                                             && gamestate.read().curr_winning_card.clone().unwrap() == card.clone(),
+                                                        is_legal: legal_cards
+                                                            .as_ref()
+                                                            .map_or(true, |cards| cards.contains(card)),
                                                         show_player: false,
                                                         show_order: false,
                                                         order: i,
@@ -1722,26 +2205,11 @@ fn GameStateComponent(
                             {
                                 (0..=gamestate().cards_to_deal)
                                     .map(|i| {
-                                        if user_config.read().username == gamestate().get_dealer()
-                                            && (i + gamestate().bids.values().map(|x| x.unwrap()).sum::<i32>())
-                                                == gamestate().cards_to_deal
-                                        {
+                                        if !legal_bids.contains(&i) {
                                             rsx! {
                                                 button {
-                                                    class: "styles::BID_BUTTON bg-bg-color",
-                                                    // disabled: true,
-                                                    onclick: move |_| {
-                                                        info!("Clicked on bid {i}");
-                                                        ws_send()
-                                                            .send(InnerMessage::GameMessage {
-                                                                msg: GameMessage {
-                                                                    username: user_config.read().username.clone(),
-                                                                    action: GameAction::Bid(i),
-                                                                    lobby: user_config.read().lobby_code.clone(),
-                                                                    timestamp: Utc::now(),
-                                                                },
-                                                            });
-                                                    },
+                                                    class: "{styles::BID_BUTTON} bg-bg-color opacity-50",
+                                                    disabled: true,
                                                     "{i}"
                                                 }
                                             }
@@ -1848,6 +2316,74 @@ fn GameStateComponent(
                                     },
                                     "Acknowledge"
                                 }
+                                {
+                                    let username = user_config.read().username.clone();
+                                    let is_leader = gamestate()
+                                        .players
+                                        .get(&username)
+                                        .map(|player| player.role == PlayerRole::Leader)
+                                        .unwrap_or(false);
+                                    match gamestate().rematch {
+                                        None if is_leader => {
+                                            rsx! {
+                                                {
+                                                    [
+                                                        (RematchSeating::Keep, "Rematch"),
+                                                        (RematchSeating::RotateDealer, "Rematch (next dealer)"),
+                                                        (RematchSeating::Shuffle, "Rematch (new seats)"),
+                                                    ]
+                                                        .into_iter()
+                                                        .map(|(seating, text)| {
+                                                            rsx! {
+                                                                button {
+                                                                    class: "{styles::STANDARD_BUTTON} text-white",
+                                                                    onclick: move |_| {
+                                                                        ws_send()
+                                                                            .send(InnerMessage::GameMessage {
+                                                                                msg: GameMessage {
+                                                                                    username: user_config.read().username.clone(),
+                                                                                    action: GameAction::Rematch(RematchOptions {
+                                                                                        seating: seating.clone(),
+                                                                                    }),
+                                                                                    lobby: user_config.read().lobby_code.clone(),
+                                                                                    timestamp: Utc::now(),
+                                                                                },
+                                                                            });
+                                                                    },
+                                                                    "{text}"
+                                                                }
+                                                            }
+                                                        })
+                                                }
+                                            }
+                                        }
+                                        Some(request) if !request.confirmed.contains(&username) => {
+                                            rsx! {
+                                                button {
+                                                    class: "{styles::STANDARD_BUTTON} text-white",
+                                                    onclick: move |_| {
+                                                        ws_send()
+                                                            .send(InnerMessage::GameMessage {
+                                                                msg: GameMessage {
+                                                                    username: user_config.read().username.clone(),
+                                                                    action: GameAction::AcceptRematch,
+                                                                    lobby: user_config.read().lobby_code.clone(),
+                                                                    timestamp: Utc::now(),
+                                                                },
+                                                            });
+                                                    },
+                                                    "Accept rematch from {request.requested_by}"
+                                                }
+                                            }
+                                        }
+                                        Some(request) => {
+                                            rsx! {
+                                                span { class: "text-sm", "Waiting for everyone to accept the rematch" }
+                                            }
+                                        }
+                                        None => rsx! {},
+                                    }
+                                }
                             }
                         }
                     }
@@ -1975,7 +2511,7 @@ fn get_trump_svg(trump: &Suit) -> Element {
                 }
             }
         ),
-        Suit::NoTrump => rsx!(
+        Suit::NoTrump | Suit::Joker => rsx!(
             svg {
                 "fill": "none",
                 height: "40",
@@ -1,6 +1,7 @@
 #![allow(warnings)]
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::env;
 use std::net::SocketAddr;
 use std::path::PathBuf;
@@ -30,11 +31,14 @@ use axum::Json;
 use axum::Router;
 use chrono::TimeDelta;
 use chrono::Utc;
+use common::ComputerTurn;
 use common::Connect;
 use common::Destination;
 use common::GameEventResult;
 use common::GameMessage;
 use common::GameState;
+use common::GameplayState;
+use common::HintRequest;
 use futures_util::StreamExt;
 use include_dir::Dir;
 use include_dir::File;
@@ -51,6 +55,7 @@ use tokio::sync::RwLock;
 use tokio::time::sleep;
 use tower_http::cors::Any;
 use tower_http::cors::CorsLayer;
+use tracing::error;
 use tracing::info;
 
 use tracing_subscriber::fmt::format::FmtSpan;
@@ -70,7 +75,13 @@ const ROOT: &str = "";
 const DEFAULT_FILES: [&str; 1] = ["index.html"];
 const NOT_FOUND: &str = "404.html";
 const STALE_GAME_TIME_DURATION_SECONDS: i64 = 30 * 60;
+// games sitting on the final standings are cleaned up sooner
+const FINISHED_GAME_TIME_DURATION_SECONDS: i64 = 10 * 60;
+// paused games are kept around for a while longer, but not forever
+const PAUSED_GAME_TIME_DURATION_SECONDS: i64 = 4 * 60 * 60;
 const STALE_GAME_THREAD_SLEEP_SECONDS: u64 = 60 * 5;
+// how often games check for computer moves and acknowledgement deadlines
+const GAME_TICK_MILLISECONDS: u64 = 100;
 
 // async fn serve_asset(path: Option<Path<String>>) -> impl IntoResponse {
 //     info!("Attempting to serve file: {:?}", path);
@@ -146,6 +157,17 @@ const STALE_GAME_THREAD_SLEEP_SECONDS: u64 = 60 * 5;
 //     }
 // }
 
+fn ready_players(room: &GameState) -> Vec<String> {
+    let mut ready = room
+        .players
+        .values()
+        .filter(|player| player.ready)
+        .map(|player| player.id.clone())
+        .collect::<Vec<String>>();
+    ready.sort();
+    ready
+}
+
 #[axum::debug_handler]
 pub async fn get_rooms(
     // State(Arc(AppState { rooms, .. })): State<Arc<Mutex<AppState>>>,
@@ -165,6 +187,7 @@ pub async fn get_rooms(
                     .map(|(roomkey, room)| Lobby {
                         name: room.lobby_code.clone(),
                         players: room.players.keys().cloned().collect::<Vec<String>>(),
+                        ready: ready_players(room),
                         max_players: room.get_max_players().clone(),
                         game_mode: room.get_game_mode().clone(),
                     })
@@ -323,6 +346,7 @@ pub async fn get_room(
                 lobby: Lobby {
                     name: room.lobby_code.clone(),
                     players: room.players.keys().cloned().collect::<Vec<String>>(),
+                    ready: ready_players(room),
                     max_players: room.get_max_players(),
                     game_mode: room.get_game_mode().clone(),
                 },
@@ -376,6 +400,44 @@ pub async fn create_room(
     }
 }
 
+/// Works out a computer move or a hint off the lock, so the ticker and every
+/// other room keep going, then hands it back to the room with `finish`.
+async fn think<T, R>(
+    lobby_code: String,
+    request: T,
+    decide: fn(&T) -> R,
+    finish: fn(&mut GameState, T, R) -> Option<GameEventResult>,
+    serverstate: Arc<RwLock<AppState>>,
+    thinking: Arc<Mutex<HashSet<String>>>,
+    toclient_send: tokio::sync::mpsc::UnboundedSender<GameEventResult>,
+) where
+    T: Send + 'static,
+    R: Send + 'static,
+{
+    let decided = tokio::task::spawn_blocking(move || {
+        let answer = decide(&request);
+        (request, answer)
+    })
+    .await;
+
+    let mut state_guard = serverstate.write().await;
+    thinking.lock().await.remove(&lobby_code);
+    let (request, answer) = match decided {
+        Ok(decided) => decided,
+        Err(err) => {
+            error!("[TICK] Thinking in {} failed: {}", lobby_code, err);
+            return;
+        }
+    };
+    let Some(game) = state_guard.rooms.get_mut(&lobby_code) else {
+        return;
+    };
+    if let Some(eventresult) = finish(game, request, answer) {
+        info!("[TICK] {} is done thinking", lobby_code);
+        toclient_send.send(eventresult).unwrap();
+    }
+}
+
 #[tokio::main]
 async fn main() {
     println!("Starting server");
@@ -434,6 +496,9 @@ async fn main() {
 
     let mut stateclone = Arc::clone(&serverstate);
     let mut stateclone_stale = Arc::clone(&serverstate);
+    let stateclone_tick = Arc::clone(&serverstate);
+    let toclient_send_tick = toclient_send.clone();
+    let thinking = Arc::new(Mutex::new(HashSet::new()));
 
     println!("Setting up game -> client loop");
     let mut game_to_client_loop = {
@@ -483,6 +548,56 @@ async fn main() {
         })
     };
 
+    let mut game_ticker = {
+        tokio::spawn(async move {
+            info!("[TICK] - starting thread");
+            let mut interval =
+                tokio::time::interval(Duration::from_millis(GAME_TICK_MILLISECONDS));
+            loop {
+                interval.tick().await;
+                let mut state_guard = stateclone_tick.write().await;
+                let mut thinking_guard = thinking.lock().await;
+                for (lobby_code, game) in state_guard.rooms.iter_mut() {
+                    // one search per room, and nothing moves on meanwhile
+                    if thinking_guard.contains(lobby_code) {
+                        continue;
+                    }
+                    // the same time for both, or `tick` could find a
+                    // computer due after all and search under the lock
+                    let now = Utc::now();
+                    if let Some(turn) = game.computer_turn_due(now) {
+                        thinking_guard.insert(lobby_code.clone());
+                        tokio::spawn(think(
+                            lobby_code.clone(),
+                            turn,
+                            ComputerTurn::decide,
+                            |game, turn, action| {
+                                game.finish_computer_turn(turn, action, Utc::now())
+                            },
+                            Arc::clone(&stateclone_tick),
+                            Arc::clone(&thinking),
+                            toclient_send_tick.clone(),
+                        ));
+                    } else if let Some(request) = game.hint_due() {
+                        thinking_guard.insert(lobby_code.clone());
+                        tokio::spawn(think(
+                            lobby_code.clone(),
+                            request,
+                            HintRequest::decide,
+                            GameState::finish_hint,
+                            Arc::clone(&stateclone_tick),
+                            Arc::clone(&thinking),
+                            toclient_send_tick.clone(),
+                        ));
+                    } else if let Some(eventresult) = game.tick(now) {
+                        info!("[TICK] Moved {} along", lobby_code);
+                        toclient_send_tick.send(eventresult).unwrap();
+                    }
+                }
+            }
+        })
+    };
+
     let mut stale_game_killer = {
         tokio::spawn(async move {
             info!("[STALE] - starting thread");
@@ -494,8 +609,20 @@ async fn main() {
 
                     let mut rooms_to_remove = vec![];
                     for (lobby_code, game) in rooms.iter_mut() {
+                        if let (true, Some(paused_at)) = (game.paused, game.paused_at) {
+                            if Utc::now().signed_duration_since(paused_at)
+                                <= TimeDelta::seconds(PAUSED_GAME_TIME_DURATION_SECONDS)
+                            {
+                                continue;
+                            }
+                        }
+                        let max_idle = if game.gameplay_state == GameplayState::End {
+                            FINISHED_GAME_TIME_DURATION_SECONDS
+                        } else {
+                            STALE_GAME_TIME_DURATION_SECONDS
+                        };
                         if Utc::now().signed_duration_since(game.updated_at)
-                            > TimeDelta::seconds(STALE_GAME_TIME_DURATION_SECONDS)
+                            > TimeDelta::seconds(max_idle)
                         {
                             info!(
                                 "[STALE] Stale game found, deleting: {} - ({} (updated_at) vs. {}(now))",
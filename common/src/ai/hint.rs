@@ -0,0 +1,165 @@
+//! Suggestions for people: the move `TrickAware` would make in their seat,
+//! and why, in a few words.
+
+use crate::{game::effective_suit, GameAction, Hint, LegalMoves, SearchBudget};
+
+use super::{
+    closest_valid_bid, expected_tricks, PlayerObservation, Strategy, TrickAware, BID_ROLLOUTS,
+};
+
+/// Cards from the queen up count as high.
+const HIGH_CARD: i32 = 12;
+
+/// What to bid or play, `None` when it isn't the player's turn to do either.
+/// The simulated rounds behind a bid stop at `budget`, like an `Expert`
+/// search does.
+pub fn hint(obs: &PlayerObservation, budget: &SearchBudget) -> Option<Hint> {
+    let hint = match obs.legal_moves()? {
+        LegalMoves::Bids(_) => {
+            let budget = SearchBudget {
+                iterations: BID_ROLLOUTS.min(budget.iterations),
+                time_ms: budget.time_ms,
+            };
+            let expected = expected_tricks(obs, &budget);
+            let bid = closest_valid_bid(obs, expected);
+            Hint {
+                action: GameAction::Bid(bid),
+                explanation: bid_explanation(obs, bid, expected),
+            }
+        }
+        LegalMoves::Cards(_) => {
+            let card = TrickAware.play(obs);
+            Hint {
+                explanation: card_explanation(obs, obs.would_win_trick(&card)),
+                action: GameAction::PlayCard(card),
+            }
+        }
+    };
+    Some(hint)
+}
+
+/// "3 trump cards, 2 high off-suit, about 2.6 tricks"
+fn bid_explanation(obs: &PlayerObservation, bid: i32, expected: f32) -> String {
+    let (trump, off_suit): (Vec<_>, Vec<_>) = obs
+        .hand
+        .iter()
+        .partition(|card| effective_suit(card, &obs.trump) == obs.trump);
+    let high = off_suit
+        .iter()
+        .filter(|card| card.value >= HIGH_CARD)
+        .count();
+
+    let mut explanation = format!(
+        "{} trump {}, {high} high off-suit, about {expected:.1} tricks",
+        trump.len(),
+        if trump.len() == 1 { "card" } else { "cards" },
+    );
+    let rounded = expected.round() as i32;
+    if rounded != bid && !obs.valid_bids().contains(&rounded) {
+        explanation.push_str(&format!(", but the dealer can't bid {rounded}"));
+    }
+    explanation
+}
+
+/// Follows the cases of `choose_card`.
+fn card_explanation(obs: &PlayerObservation, wins_trick: bool) -> String {
+    let bid = obs.bid().unwrap_or(0);
+    let needed = bid - obs.tricks_won();
+    let still_need = format!(
+        "You need {needed} more {}",
+        if needed == 1 { "trick" } else { "tricks" }
+    );
+    let have_bid = format!("You have your {bid}");
+
+    match (needed > 0, obs.leads(), wins_trick) {
+        (true, true, _) => format!("{still_need}, lead your strongest card"),
+        (false, true, _) => format!("{have_bid}, lead your weakest card"),
+        (true, false, true) if obs.plays_last() => {
+            format!("{still_need}, this is the cheapest card that takes the trick")
+        }
+        (true, false, true) => {
+            format!("{still_need}, this is your best chance to hold the trick")
+        }
+        (true, false, false) => {
+            format!("{still_need}, but nothing takes this trick, throw away your weakest")
+        }
+        (false, false, false) => {
+            format!("{have_bid}, stay under with your highest losing card")
+        }
+        (false, false, true) if obs.plays_last() => {
+            format!("{have_bid}, but every card takes this trick, get rid of the most dangerous")
+        }
+        (false, false, true) => {
+            format!("{have_bid}, every card wins for now, play low so others can go over it")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{Card, GameplayState, PlayState, Suit};
+
+    use super::*;
+
+    fn bidding(hand: Vec<Card>, trump: Suit) -> PlayerObservation {
+        let game = crate::GameState::new("lobby".to_string());
+        let mut obs = PlayerObservation::new(&game, "me", &hand);
+        obs.player_order = vec!["me".to_string(), "you".to_string()];
+        obs.curr_dealer = "you".to_string();
+        obs.curr_player_turn = Some("me".to_string());
+        obs.gameplay_state = GameplayState::Bid;
+        obs.cards_to_deal = hand.len() as i32;
+        obs.bids = HashMap::new();
+        obs.trump = trump;
+        obs
+    }
+
+    #[test]
+    fn test_bid_hint_counts_trump_and_high_cards() {
+        let hand = vec![
+            Card::new(Suit::Heart, 14),
+            Card::new(Suit::Heart, 3),
+            Card::new(Suit::Spade, 13),
+            Card::new(Suit::Club, 12),
+            Card::new(Suit::Club, 4),
+        ];
+        let hint = hint(&bidding(hand, Suit::Heart), &SearchBudget::default()).expect("No hint");
+        assert!(matches!(hint.action, GameAction::Bid(_)));
+        assert!(
+            hint.explanation
+                .starts_with("2 trump cards, 2 high off-suit, about "),
+            "{}",
+            hint.explanation
+        );
+    }
+
+    #[test]
+    fn test_card_hint_on_turn_only() {
+        let hand = vec![
+            Card::new(Suit::Heart, 14),
+            Card::new(Suit::Heart, 3),
+            Card::new(Suit::Spade, 2),
+        ];
+        let mut obs = bidding(hand, Suit::Spade);
+        obs.gameplay_state = GameplayState::Play(PlayState {
+            hand_num: 1,
+            hands: 2,
+        });
+        obs.bids = HashMap::from([("me".to_string(), Some(1)), ("you".to_string(), Some(0))]);
+
+        let hint = hint(&obs, &SearchBudget::default()).expect("No hint");
+        assert_eq!(
+            hint.action,
+            GameAction::PlayCard(Card::new(Suit::Heart, 14))
+        );
+        assert_eq!(
+            hint.explanation,
+            "You need 1 more trick, lead your strongest card"
+        );
+
+        obs.curr_player_turn = Some("you".to_string());
+        assert_eq!(super::hint(&obs, &SearchBudget::default()), None);
+    }
+}
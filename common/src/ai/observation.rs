@@ -0,0 +1,207 @@
+//! What one seat can see of a game: its own hand and everything that is
+//! public at the table. Strategies only ever get one of these, so they can't
+//! peek at the deck or at anyone else's cards by accident.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::{check_card_legal, find_winning_card, validate_bid},
+    Card, FirstLeadPolicy, GameState, GameplayState, IdenticalCardRule, LegalMoves, Suit,
+    TrickRecord,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerObservation {
+    pub username: String,
+    pub hand: Vec<Card>,
+    pub gameplay_state: GameplayState,
+    /// Everyone at the table, in playing order.
+    pub player_order: Vec<String>,
+    pub curr_dealer: String,
+    pub curr_player_turn: Option<String>,
+    pub curr_round: i32,
+    pub cards_to_deal: i32,
+    pub trump: Suit,
+    pub trump_played_in_round: bool,
+    pub bids: HashMap<String, Option<i32>>,
+    pub wins: HashMap<String, i32>,
+    pub score: HashMap<String, i32>,
+    /// Tricks finished this round.
+    pub tricks: Vec<TrickRecord>,
+    pub curr_played_cards: Vec<Card>,
+    pub acks: Vec<String>,
+    pub decks: usize,
+    pub jokers: usize,
+    pub first_lead: FirstLeadPolicy,
+    pub identical_card_rule: IdenticalCardRule,
+}
+
+impl PlayerObservation {
+    /// `hand` is passed in rather than read from `gamestate`, since clients
+    /// only get their own hand encrypted.
+    pub fn new(gamestate: &GameState, username: &str, hand: &[Card]) -> PlayerObservation {
+        let options = gamestate.get_setup_game_options();
+        PlayerObservation {
+            username: username.to_string(),
+            hand: hand.to_vec(),
+            gameplay_state: gamestate.gameplay_state.clone(),
+            player_order: gamestate.player_order.clone(),
+            curr_dealer: gamestate.curr_dealer.clone(),
+            curr_player_turn: gamestate.curr_player_turn.clone(),
+            curr_round: gamestate.curr_round,
+            cards_to_deal: gamestate.cards_to_deal,
+            trump: gamestate.trump.clone(),
+            trump_played_in_round: gamestate.trump_played_in_round,
+            bids: gamestate.bids.clone(),
+            wins: gamestate.wins.clone(),
+            score: gamestate.score.clone(),
+            tricks: gamestate.tricks.clone(),
+            curr_played_cards: gamestate.curr_played_cards.clone(),
+            acks: gamestate.acks.clone(),
+            decks: options.decks,
+            jokers: options.jokers,
+            first_lead: options.first_lead.clone(),
+            identical_card_rule: options.identical_card_rule.clone(),
+        }
+    }
+
+    pub fn is_dealer(&self) -> bool {
+        self.curr_dealer == self.username
+    }
+
+    pub fn bid(&self) -> Option<i32> {
+        self.bids.get(&self.username).cloned().flatten()
+    }
+
+    pub fn tricks_won(&self) -> i32 {
+        self.wins.get(&self.username).cloned().unwrap_or(0)
+    }
+
+    /// Bids that are allowed right now.
+    pub fn valid_bids(&self) -> Vec<i32> {
+        (0..=self.cards_to_deal)
+            .filter(|bid| {
+                validate_bid(bid, self.cards_to_deal, &self.bids, self.is_dealer()).is_ok()
+            })
+            .collect()
+    }
+
+    /// Cards in hand that can be played on the current trick.
+    pub fn legal_cards(&self) -> Vec<Card> {
+        self.hand
+            .iter()
+            .filter(|card| {
+                check_card_legal(
+                    &self.hand,
+                    card,
+                    &self.curr_played_cards,
+                    &self.trump,
+                    self.trump_played_in_round,
+                )
+                .is_ok()
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Bids or cards allowed right now, `None` when it isn't this player's
+    /// turn to bid or play.
+    pub fn legal_moves(&self) -> Option<LegalMoves> {
+        if self.curr_player_turn.as_ref() != Some(&self.username) {
+            return None;
+        }
+        match self.gameplay_state {
+            GameplayState::Bid => Some(LegalMoves::Bids(self.valid_bids())),
+            GameplayState::Play(_) => Some(LegalMoves::Cards(self.legal_cards())),
+            _ => None,
+        }
+    }
+
+    pub fn leads(&self) -> bool {
+        self.curr_played_cards.is_empty()
+    }
+
+    pub fn plays_last(&self) -> bool {
+        self.curr_played_cards.len() + 1 >= self.player_order.len()
+    }
+
+    /// Whether `card` would take the trick as it stands.
+    pub fn would_win_trick(&self, card: &Card) -> bool {
+        let mut played = self.curr_played_cards.clone();
+        played.push(card.clone());
+        find_winning_card(played, self.trump.clone(), &self.identical_card_rule) == *card
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::ai::tests::game_until;
+
+    use super::*;
+
+    /// Three players a trick into a five card round, with a password set.
+    fn game_in_progress() -> GameState {
+        let mut game = game_until(5, |game| {
+            !game.tricks.is_empty() && game.curr_played_cards.len() == 1
+        });
+        game.setup_game_options.password = Some("hunter2".to_string());
+        game
+    }
+
+    #[test]
+    fn test_observation_shows_no_other_hands() {
+        let game = game_in_progress();
+        let obs = game.observation_for("p1");
+        assert_eq!(obs.hand, game.players["p1"].hand);
+
+        let visible: HashSet<usize> = obs
+            .hand
+            .iter()
+            .chain(obs.tricks.iter().flat_map(|trick| trick.cards.iter()))
+            .chain(obs.curr_played_cards.iter())
+            .map(|card| card.id)
+            .collect();
+        let hidden = game
+            .deck
+            .iter()
+            .chain(game.players["p2"].hand.iter())
+            .chain(game.players["p3"].hand.iter());
+        for card in hidden {
+            assert!(!visible.contains(&card.id), "{card:?} is visible to p1");
+        }
+
+        let json = serde_json::to_string(&obs).unwrap();
+        assert!(!json.contains("hunter2"));
+        for secret in game.players_secrets.values() {
+            assert!(!json.contains(secret.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_observation_is_the_same_whatever_the_others_hold() {
+        let game = game_in_progress();
+        let mut hidden = game.clone();
+        hidden.deck = vec![];
+        hidden.players_secrets = HashMap::new();
+        for (username, player) in hidden.players.iter_mut() {
+            if username != "p1" {
+                player.hand = vec![];
+                player.encrypted_hand = String::new();
+            }
+        }
+        assert_eq!(hidden.observation_for("p1"), game.observation_for("p1"));
+    }
+
+    #[test]
+    fn test_legal_cards_match_the_game() {
+        let game = game_in_progress();
+        for player in ["p1", "p2", "p3"] {
+            let obs = game.observation_for(player);
+            assert_eq!(obs.legal_cards(), game.legal_cards(&obs.hand));
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! Line protocol for engines, computer players written in any language that
+//! play through `ai-client`. The client starts the engine and writes one JSON
+//! object per line to its stdin, the engine answers every request with one
+//! line on its stdout:
+//!
+//! ```text
+//! > {"type":"hello","version":1}
+//! < {"type":"hello","version":1,"name":"my engine"}
+//! > {"type":"move","observation":{...},"legal":{"bids":[0,1,3]}}
+//! < {"type":"move","action":{"bid":1}}
+//! > {"type":"move","observation":{...},"legal":{"cards":[{"id":12,"suit":"heart","value":9,"played_by":null}]}}
+//! < {"type":"move","action":{"playcard":{"id":12,"suit":"heart","value":9,"played_by":null}}}
+//! > {"type":"quit"}
+//! ```
+//!
+//! Observations are `PlayerObservation`s, cards are `Card`s and moves are
+//! `GameAction::Bid` or `GameAction::PlayCard`, all in their JSON form.
+//! Anything that changes that form bumps `VERSION`. Engines can log to
+//! stderr.
+
+use std::io::{self, BufRead, Write};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{GameAction, LegalMoves};
+
+use super::{PlayerObservation, Strategy};
+
+pub const VERSION: u32 = 1;
+
+/// Client to engine.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Request {
+    /// Sent first, answered with a `Reply::Hello` with the version the
+    /// engine speaks.
+    Hello { version: u32 },
+    /// Answered with a `Reply::Move` that is one of `legal`.
+    Move {
+        observation: Box<PlayerObservation>,
+        legal: LegalMoves,
+    },
+    /// Sent last, the engine should exit.
+    Quit,
+}
+
+/// Engine to client.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Reply {
+    Hello { version: u32, name: String },
+    Move { action: GameAction },
+}
+
+/// Writes `message` as one line and flushes it.
+pub fn write_line<T: Serialize>(output: &mut impl Write, message: &T) -> io::Result<()> {
+    let line = serde_json::to_string(message)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    writeln!(output, "{line}")?;
+    output.flush()
+}
+
+/// Reads the next non-empty line, `None` at the end of the input.
+pub fn read_line<T: DeserializeOwned>(input: &mut impl BufRead) -> io::Result<Option<T>> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.trim().is_empty() {
+            break;
+        }
+    }
+    serde_json::from_str(&line)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Answers requests from `input` with the moves of `strategy`, until a
+/// `Request::Quit` or the end of the input.
+pub fn serve(
+    strategy: &dyn Strategy,
+    name: &str,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<()> {
+    while let Some(request) = read_line::<Request>(&mut input)? {
+        let reply = match request {
+            Request::Hello { .. } => Reply::Hello {
+                version: VERSION,
+                name: name.to_string(),
+            },
+            Request::Move { observation, legal } => Reply::Move {
+                action: match legal {
+                    LegalMoves::Bids(_) => GameAction::Bid(strategy.bid(&observation)),
+                    LegalMoves::Cards(_) => GameAction::PlayCard(strategy.play(&observation)),
+                },
+            },
+            Request::Quit => break,
+        };
+        write_line(&mut output, &reply)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use serde_json::json;
+
+    use crate::{
+        ai::{tests::game_until, TrickAware},
+        Card, GameplayState, Suit,
+    };
+
+    use super::*;
+
+    fn serve_lines(requests: &[Request]) -> Vec<Reply> {
+        let mut input = vec![];
+        for request in requests {
+            write_line(&mut input, request).unwrap();
+        }
+        let mut output = vec![];
+        serve(&TrickAware, "test", input.as_slice(), &mut output).unwrap();
+        let mut replies = vec![];
+        let mut output = output.as_slice();
+        while let Some(reply) = read_line(&mut output).unwrap() {
+            replies.push(reply);
+        }
+        replies
+    }
+
+    #[test]
+    fn test_serve_answers_with_legal_moves() {
+        let bidding = game_until(4, |game| game.gameplay_state == GameplayState::Bid);
+        let bid_obs = bidding.observation_for(bidding.curr_player_turn.as_ref().unwrap());
+        let playing = game_until(4, |game| {
+            matches!(game.gameplay_state, GameplayState::Play(_))
+        });
+        let play_obs = playing.observation_for(playing.curr_player_turn.as_ref().unwrap());
+        let (Some(bids), Some(cards)) = (bid_obs.legal_moves(), play_obs.legal_moves()) else {
+            panic!("Nothing to do");
+        };
+
+        let replies = serve_lines(&[
+            Request::Hello { version: VERSION },
+            Request::Move {
+                observation: Box::new(bid_obs),
+                legal: bids.clone(),
+            },
+            Request::Move {
+                observation: Box::new(play_obs),
+                legal: cards.clone(),
+            },
+            Request::Quit,
+            Request::Hello { version: VERSION },
+        ]);
+
+        assert_eq!(replies.len(), 3, "{replies:?}");
+        assert_eq!(
+            replies[0],
+            Reply::Hello {
+                version: VERSION,
+                name: "test".to_string()
+            }
+        );
+        match (&replies[1], &bids) {
+            (
+                Reply::Move {
+                    action: GameAction::Bid(bid),
+                },
+                LegalMoves::Bids(bids),
+            ) => assert!(bids.contains(bid)),
+            other => panic!("Not a legal bid: {other:?}"),
+        }
+        match (&replies[2], &cards) {
+            (
+                Reply::Move {
+                    action: GameAction::PlayCard(card),
+                },
+                LegalMoves::Cards(cards),
+            ) => assert!(cards.contains(card)),
+            other => panic!("Not a legal card: {other:?}"),
+        }
+    }
+
+    /// Engines in other languages rely on this exact form, a failure here
+    /// means `VERSION` has to go up.
+    #[test]
+    fn test_encoding_of_version_1() {
+        assert_eq!(
+            json!(Request::Hello { version: 1 }),
+            json!({"type": "hello", "version": 1})
+        );
+        assert_eq!(json!(Request::Quit), json!({"type": "quit"}));
+
+        let card = Card {
+            id: 12,
+            suit: Suit::Heart,
+            value: 9,
+            played_by: None,
+        };
+        let card_json = json!({"id": 12, "suit": "heart", "value": 9, "played_by": null});
+        assert_eq!(
+            json!(LegalMoves::Cards(vec![card.clone()])),
+            json!({ "cards": [card_json] })
+        );
+        assert_eq!(json!(LegalMoves::Bids(vec![0, 2])), json!({"bids": [0, 2]}));
+        assert_eq!(
+            serde_json::from_value::<Reply>(json!({"type": "move", "action": {"bid": 1}})).unwrap(),
+            Reply::Move {
+                action: GameAction::Bid(1)
+            }
+        );
+        assert_eq!(
+            serde_json::from_value::<Reply>(
+                json!({"type": "move", "action": {"playcard": card_json}})
+            )
+            .unwrap(),
+            Reply::Move {
+                action: GameAction::PlayCard(card)
+            }
+        );
+
+        let game = game_until(4, |game| game.gameplay_state == GameplayState::Bid);
+        let observation = json!(game.observation_for("p1"));
+        let fields: BTreeSet<&str> = observation
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|key| key.as_str())
+            .collect();
+        assert_eq!(
+            fields,
+            BTreeSet::from([
+                "username",
+                "hand",
+                "gameplay_state",
+                "player_order",
+                "curr_dealer",
+                "curr_player_turn",
+                "curr_round",
+                "cards_to_deal",
+                "trump",
+                "trump_played_in_round",
+                "bids",
+                "wins",
+                "score",
+                "tricks",
+                "curr_played_cards",
+                "acks",
+                "decks",
+                "jokers",
+                "first_lead",
+                "identical_card_rule",
+            ])
+        );
+        assert_eq!(observation["gameplay_state"], json!("Bid"));
+    }
+}
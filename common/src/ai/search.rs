@@ -0,0 +1,489 @@
+//! Monte Carlo search over determinized deals: the cards a seat can't see are
+//! dealt out at random, consistent with everything played so far, and the
+//! rest of the round is played out for every candidate bid or card.
+
+use std::{collections::HashSet, time::Instant};
+
+use crate::{
+    create_decks,
+    game::{check_card_legal, effective_suit, find_winning_card},
+    Card, FirstLeadPolicy, IdenticalCardRule, SearchBudget, Suit,
+};
+
+use super::{choose_card, closest_valid_bid, get_bidding_strength, PlayerObservation, Strategy};
+
+/// Simulated rounds behind every `simulated_bid`.
+pub const BID_ROLLOUTS: usize = 200;
+
+/// Attempts at dealing the hidden cards so every void seen so far is
+/// respected, before dealing them without looking at voids.
+const DEAL_ATTEMPTS: usize = 20;
+
+/// Picks the bid or card with the best average score over simulated rounds.
+/// Everyone in the simulations, itself included, plays
+/// like `TrickAware`.
+pub struct MonteCarlo {
+    pub budget: SearchBudget,
+}
+
+impl Strategy for MonteCarlo {
+    fn bid(&self, obs: &PlayerObservation) -> i32 {
+        let candidates = obs.valid_bids();
+        let table = Table::new(obs);
+
+        let scores = self.search(candidates.len(), |candidate| {
+            let mut round = table.deal()?;
+            round.estimate_bids();
+            round.bids[table.me] = candidates[candidate];
+            round.turn = round.first_leader(obs);
+            Some(round.play_out(table.me))
+        });
+        candidates[best(&scores)]
+    }
+
+    fn play(&self, obs: &PlayerObservation) -> Card {
+        let candidates = obs.legal_cards();
+        if candidates.len() == 1 {
+            return candidates[0].clone();
+        }
+        let table = Table::new(obs);
+
+        let scores = self.search(candidates.len(), |candidate| {
+            let mut round = table.deal()?;
+            round.play(candidates[candidate].clone());
+            Some(round.play_out(table.me))
+        });
+        candidates[best(&scores)].clone()
+    }
+}
+
+/// Average number of tricks the seat takes over up to `budget.iterations`
+/// simulated rounds, fewer if `budget.time_ms` runs out first, with the cards
+/// it can't see dealt at random and everyone playing like
+/// `TrickAware`. Seats yet to bid, and the seat itself, are taken to bid their
+/// hand strength. Unlike the hand strength alone this accounts for the number
+/// of players, who leads, the bids already made and rounds without trump.
+pub fn expected_tricks(obs: &PlayerObservation, budget: &SearchBudget) -> f32 {
+    let started = Instant::now();
+    let table = Table::new(obs);
+    let mut tricks = 0;
+    let mut played = 0;
+    for _ in 0..budget.iterations {
+        if started.elapsed().as_millis() >= budget.time_ms as u128 {
+            break;
+        }
+        let Some(mut round) = table.deal() else {
+            continue;
+        };
+        round.estimate_bids();
+        round.bids[table.me] = get_bidding_strength(&obs.hand, &obs.trump);
+        round.turn = round.first_leader(obs);
+        round.play_to_end();
+        tricks += round.wins[table.me];
+        played += 1;
+    }
+    if played == 0 {
+        return get_bidding_strength(&obs.hand, &obs.trump) as f32;
+    }
+    tricks as f32 / played as f32
+}
+
+/// The valid bid closest to `expected_tricks`.
+pub fn simulated_bid(obs: &PlayerObservation, budget: &SearchBudget) -> i32 {
+    closest_valid_bid(obs, expected_tricks(obs, budget))
+}
+
+impl MonteCarlo {
+    /// Runs `simulate` for each candidate in turn until the budget runs out,
+    /// and returns the total score of each candidate.
+    fn search(
+        &self,
+        num_candidates: usize,
+        mut simulate: impl FnMut(usize) -> Option<i32>,
+    ) -> Vec<i32> {
+        let started = Instant::now();
+        let mut scores = vec![0; num_candidates];
+        let mut iterations = 0;
+        while iterations < self.budget.iterations.max(num_candidates)
+            && started.elapsed().as_millis() < self.budget.time_ms as u128
+        {
+            let candidate = iterations % num_candidates;
+            if let Some(score) = simulate(candidate) {
+                scores[candidate] += score;
+            }
+            iterations += 1;
+        }
+        scores
+    }
+}
+
+/// Index of the highest score, the first one on ties.
+fn best(scores: &[i32]) -> usize {
+    let mut best = 0;
+    for (i, score) in scores.iter().enumerate() {
+        if *score > scores[best] {
+            best = i;
+        }
+    }
+    best
+}
+
+/// Everything one seat knows about the round in progress.
+struct Table {
+    seats: Vec<String>,
+    me: usize,
+    hand: Vec<Card>,
+    /// Cards nobody has shown yet.
+    unseen: Vec<Card>,
+    /// How many cards every seat still holds.
+    hand_sizes: Vec<usize>,
+    /// Suits each seat has shown it doesn't have.
+    voids: Vec<Vec<Suit>>,
+    trump: Suit,
+    identical_card_rule: IdenticalCardRule,
+    trick: Vec<Card>,
+    trump_played: bool,
+    turn: usize,
+    bids: Vec<i32>,
+    wins: Vec<i32>,
+}
+
+impl Table {
+    fn new(obs: &PlayerObservation) -> Table {
+        let seats = obs.player_order.clone();
+        let seat_of =
+            |player: &Option<String>| seats.iter().position(|seat| Some(seat) == player.as_ref());
+        let trump = obs.trump.clone();
+
+        let mut hand_sizes = vec![obs.cards_to_deal as usize; seats.len()];
+        let mut voids: Vec<Vec<Suit>> = vec![vec![]; seats.len()];
+        let mut seen: HashSet<usize> = obs.hand.iter().map(|card| card.id).collect();
+        let tricks = obs
+            .tricks
+            .iter()
+            .map(|trick| &trick.cards)
+            .chain(std::iter::once(&obs.curr_played_cards));
+        for trick in tricks {
+            let Some(led) = trick.first().map(|card| effective_suit(card, &trump)) else {
+                continue;
+            };
+            for card in trick {
+                seen.insert(card.id);
+                // cards of players who forfeited stay in the tricks after they
+                // leave the table
+                let Some(seat) = seat_of(&card.played_by) else {
+                    continue;
+                };
+                hand_sizes[seat] -= 1;
+                if led != Suit::Joker
+                    && effective_suit(card, &trump) != led
+                    && !voids[seat].contains(&led)
+                {
+                    voids[seat].push(led.clone());
+                }
+            }
+        }
+
+        let me = seats
+            .iter()
+            .position(|seat| *seat == obs.username)
+            .expect("Player is not at the table");
+        let bids = seats
+            .iter()
+            .map(|seat| obs.bids.get(seat).cloned().flatten().unwrap_or(-1))
+            .collect();
+        let wins = seats
+            .iter()
+            .map(|seat| obs.wins.get(seat).cloned().unwrap_or(0))
+            .collect();
+        let turn = obs
+            .curr_player_turn
+            .as_ref()
+            .and_then(|player| seats.iter().position(|seat| seat == player))
+            .unwrap_or(me);
+
+        Table {
+            me,
+            hand: obs.hand.clone(),
+            unseen: create_decks(obs.decks, obs.jokers)
+                .into_iter()
+                .filter(|card| !seen.contains(&card.id))
+                .collect(),
+            hand_sizes,
+            voids,
+            trump,
+            identical_card_rule: obs.identical_card_rule.clone(),
+            trick: obs.curr_played_cards.clone(),
+            trump_played: obs.trump_played_in_round,
+            turn,
+            bids,
+            wins,
+            seats,
+        }
+    }
+
+    /// A random deal of the unseen cards to the other seats, respecting the
+    /// voids they have shown if possible.
+    fn deal(&self) -> Option<Round> {
+        let hands = (0..DEAL_ATTEMPTS)
+            .find_map(|_| self.try_deal(true))
+            .or_else(|| self.try_deal(false))?;
+        Some(Round {
+            seats: self.seats.clone(),
+            hands,
+            trump: self.trump.clone(),
+            identical_card_rule: self.identical_card_rule.clone(),
+            trick: self.trick.clone(),
+            trump_played: self.trump_played,
+            turn: self.turn,
+            bids: self.bids.clone(),
+            wins: self.wins.clone(),
+        })
+    }
+
+    fn try_deal(&self, respect_voids: bool) -> Option<Vec<Vec<Card>>> {
+        let mut unseen = self.unseen.clone();
+        fastrand::shuffle(&mut unseen);
+
+        let mut hands = vec![vec![]; self.seats.len()];
+        hands[self.me] = self.hand.clone();
+        // the most constrained seats pick first
+        let mut others: Vec<usize> = (0..self.seats.len()).filter(|s| *s != self.me).collect();
+        others.sort_by_key(|seat| std::cmp::Reverse(self.voids[*seat].len()));
+        for seat in others {
+            while hands[seat].len() < self.hand_sizes[seat] {
+                let pos = unseen.iter().position(|card| {
+                    !respect_voids || !self.voids[seat].contains(&effective_suit(card, &self.trump))
+                })?;
+                hands[seat].push(unseen.swap_remove(pos));
+            }
+        }
+        Some(hands)
+    }
+}
+
+/// One possible version of the rest of the round, with every hand known.
+#[derive(Clone)]
+struct Round {
+    seats: Vec<String>,
+    hands: Vec<Vec<Card>>,
+    trump: Suit,
+    identical_card_rule: IdenticalCardRule,
+    trick: Vec<Card>,
+    trump_played: bool,
+    turn: usize,
+    /// -1 for seats that haven't bid yet.
+    bids: Vec<i32>,
+    wins: Vec<i32>,
+}
+
+impl Round {
+    /// Bids for the seats that haven't bid yet, from their simulated hands.
+    fn estimate_bids(&mut self) {
+        for seat in 0..self.seats.len() {
+            if self.bids[seat] < 0 {
+                self.bids[seat] = get_bidding_strength(&self.hands[seat], &self.trump);
+            }
+        }
+    }
+
+    fn first_leader(&self, obs: &PlayerObservation) -> usize {
+        let dealer = self
+            .seats
+            .iter()
+            .position(|seat| *seat == obs.curr_dealer)
+            .unwrap_or(0);
+        let left_of_dealer = (dealer + 1) % self.seats.len();
+        match obs.first_lead {
+            FirstLeadPolicy::LeftOfDealer => left_of_dealer,
+            // ties go to the closest to the dealer's left
+            FirstLeadPolicy::HighestBidder => (0..self.seats.len())
+                .map(|i| (left_of_dealer + i) % self.seats.len())
+                .fold(left_of_dealer, |leader, seat| {
+                    if self.bids[seat] > self.bids[leader] {
+                        seat
+                    } else {
+                        leader
+                    }
+                }),
+        }
+    }
+
+    fn legal_cards(&self) -> Vec<Card> {
+        let hand = &self.hands[self.turn];
+        hand.iter()
+            .filter(|card| {
+                check_card_legal(hand, card, &self.trick, &self.trump, self.trump_played).is_ok()
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn wins_trick(&self, card: &Card) -> bool {
+        let mut played = self.trick.clone();
+        played.push(card.clone());
+        find_winning_card(played, self.trump.clone(), &self.identical_card_rule) == *card
+    }
+
+    /// Plays `card` for the seat whose turn it is.
+    fn play(&mut self, mut card: Card) {
+        let hand = &mut self.hands[self.turn];
+        let pos = hand
+            .iter()
+            .position(|c| c.id == card.id)
+            .expect("Card is not in hand");
+        hand.swap_remove(pos);
+        if effective_suit(&card, &self.trump) == self.trump {
+            self.trump_played = true;
+        }
+        card.played_by = Some(self.seats[self.turn].clone());
+        self.trick.push(card);
+
+        if self.trick.len() < self.seats.len() {
+            self.turn = (self.turn + 1) % self.seats.len();
+            return;
+        }
+        let winning_card = find_winning_card(
+            std::mem::take(&mut self.trick),
+            self.trump.clone(),
+            &self.identical_card_rule,
+        );
+        let winner = self
+            .seats
+            .iter()
+            .position(|seat| Some(seat) == winning_card.played_by.as_ref())
+            .expect("Trick won by someone not at the table");
+        self.wins[winner] += 1;
+        self.turn = winner;
+    }
+
+    /// Plays the round to the end and returns the score of seat `me`.
+    fn play_out(mut self, me: usize) -> i32 {
+        self.play_to_end();
+        if self.wins[me] == self.bids[me] {
+            self.bids[me] + 10
+        } else {
+            0
+        }
+    }
+
+    fn play_to_end(&mut self) {
+        while !self.hands[self.turn].is_empty() {
+            let card = choose_card(
+                &self.legal_cards(),
+                &self.trump,
+                self.wins[self.turn] < self.bids[self.turn],
+                self.trick.is_empty(),
+                self.trick.len() + 1 >= self.seats.len(),
+                |card| self.wins_trick(card),
+            );
+            self.play(card);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ai::tests::game_until, GameState, LeavePolicy};
+
+    use super::*;
+
+    /// Three players a couple of tricks into a five card round.
+    fn game_in_progress() -> GameState {
+        game_until(5, |game| {
+            game.tricks.len() >= 2 && game.curr_played_cards.is_empty()
+        })
+    }
+
+    #[test]
+    fn test_deals_only_unseen_cards_in_the_right_amounts() {
+        let game = game_in_progress();
+        let player = game.curr_player_turn.clone().unwrap();
+        let hand = game.players[&player].hand.clone();
+        let table = Table::new(&game.observation_for(&player));
+        let played: Vec<usize> = game
+            .tricks
+            .iter()
+            .flat_map(|trick| trick.cards.iter().map(|card| card.id))
+            .collect();
+
+        for _ in 0..50 {
+            let round = table.deal().expect("Could not deal");
+            assert_eq!(round.hands[table.me], hand);
+            let mut ids = vec![];
+            for (seat, cards) in round.hands.iter().enumerate() {
+                assert_eq!(cards.len(), game.players[&table.seats[seat]].hand.len());
+                ids.extend(cards.iter().map(|card| card.id));
+            }
+            assert!(ids.iter().all(|id| !played.contains(id)));
+            let unique: HashSet<usize> = ids.iter().cloned().collect();
+            assert_eq!(unique.len(), ids.len());
+
+            if let Some(hands) = table.try_deal(true) {
+                for (seat, cards) in hands.iter().enumerate() {
+                    assert!(cards.iter().all(
+                        |card| !table.voids[seat].contains(&effective_suit(card, &table.trump))
+                    ));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_ignores_hidden_information() {
+        let game = game_in_progress();
+        let player = game.curr_player_turn.clone().unwrap();
+        let hand = game.players[&player].hand.clone();
+        let expert = MonteCarlo {
+            budget: SearchBudget {
+                iterations: 200,
+                time_ms: 10_000,
+            },
+        };
+
+        // what everyone else holds, and the rest of the deck, makes no
+        // difference to the search
+        let mut hidden = game.clone();
+        hidden.deck = vec![];
+        for (username, other) in hidden.players.iter_mut() {
+            if *username != player {
+                other.hand = vec![];
+            }
+        }
+
+        fastrand::seed(11);
+        let card = expert.play(&game.observation_for(&player));
+        fastrand::seed(11);
+        assert_eq!(expert.play(&hidden.observation_for(&player)), card);
+        assert!(game.legal_cards(&hand).contains(&card));
+    }
+
+    #[test]
+    fn test_search_after_a_player_forfeits() {
+        let mut game = game_in_progress();
+        game.setup_game_options.on_leave = LeavePolicy::Forfeit;
+        let leaving = game.player_order[0].clone();
+        game.leave_game(&leaving);
+        let played: Vec<usize> = game
+            .tricks
+            .iter()
+            .flat_map(|trick| trick.cards.iter())
+            .filter(|card| card.played_by.as_ref() == Some(&leaving))
+            .map(|card| card.id)
+            .collect();
+        assert!(!played.is_empty());
+
+        let player = game.curr_player_turn.clone().unwrap();
+        let hand = game.players[&player].hand.clone();
+        let table = Table::new(&game.observation_for(&player));
+        assert!(table.unseen.iter().all(|card| !played.contains(&card.id)));
+        let expert = MonteCarlo {
+            budget: SearchBudget {
+                iterations: 50,
+                time_ms: 10_000,
+            },
+        };
+        let card = expert.play(&game.observation_for(&player));
+        assert!(game.legal_cards(&hand).contains(&card));
+    }
+}
@@ -0,0 +1,303 @@
+//! Card values fitted to self-play: how many tricks a card adds to a hand, by
+//! rank, whether it is trump, and how many cards were dealt. The `tune` binary
+//! in `ai-client` fits and writes them, `CardValues::load` reads them back.
+//! `card_value_matrix.json` holds the ones computer players bid with.
+
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{game::effective_suit, Card, GameState, Suit};
+
+use super::{closest_valid_bid, PlayerObservation, Strategy, TrickAware, CARD_VALUE_MATRIX};
+
+/// Ranks in a deck, 14 is the ace.
+const RANKS: std::ops::RangeInclusive<i32> = 2..=14;
+/// Plain ranks, trump ranks, jokers.
+const FEATURES: usize = 13 + 13 + 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CardValues {
+    /// Values by cards dealt. Hand sizes that aren't here use the closest one
+    /// that is.
+    pub hand_sizes: BTreeMap<i32, HandSizeValues>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HandSizeValues {
+    /// Tricks added by a card outside trump, by rank.
+    pub plain: BTreeMap<i32, f32>,
+    /// Tricks added by a trump card, by rank.
+    pub trump: BTreeMap<i32, f32>,
+    pub joker: f32,
+}
+
+/// One player's hand in a finished round and the tricks it took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandSample {
+    pub hand: Vec<Card>,
+    pub trump: Suit,
+    pub tricks_won: i32,
+}
+
+impl Default for CardValues {
+    /// The built-in `card_value_matrix.json`.
+    fn default() -> Self {
+        CARD_VALUE_MATRIX.clone()
+    }
+}
+
+impl CardValues {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<CardValues> {
+        let text = fs::read_to_string(path)?;
+        serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, text)
+    }
+
+    /// Tricks `hand` is expected to take with `trump`.
+    pub fn expected_tricks(&self, hand: &[Card], trump: &Suit) -> f32 {
+        let hand_size = hand.len() as i32;
+        let Some(values) = self
+            .hand_sizes
+            .iter()
+            .min_by_key(|(size, _)| (**size - hand_size).abs())
+            .map(|(_, values)| values)
+        else {
+            return 0.0;
+        };
+        hand.iter()
+            .map(|card| match feature(card, trump) {
+                i if i < 13 => values.plain.get(&(i as i32 + 2)).cloned().unwrap_or(0.0),
+                i if i < 26 => values.trump.get(&(i as i32 - 11)).cloned().unwrap_or(0.0),
+                _ => values.joker,
+            })
+            .sum()
+    }
+
+    /// Least squares fit of the tricks taken to the cards held, separately for
+    /// every hand size. `ridge` pulls the values of cards that were rarely
+    /// held towards 0.
+    pub fn fit(samples: &[HandSample], ridge: f64) -> CardValues {
+        let mut by_size: BTreeMap<i32, Vec<&HandSample>> = BTreeMap::new();
+        for sample in samples {
+            by_size
+                .entry(sample.hand.len() as i32)
+                .or_default()
+                .push(sample);
+        }
+
+        let hand_sizes = by_size
+            .into_iter()
+            .map(|(size, samples)| {
+                // normal equations (X'X + ridge I) w = X'y
+                let mut xtx = vec![vec![0.0; FEATURES]; FEATURES];
+                let mut xty = vec![0.0; FEATURES];
+                for sample in samples {
+                    let mut x = [0.0; FEATURES];
+                    for card in sample.hand.iter() {
+                        x[feature(card, &sample.trump)] += 1.0;
+                    }
+                    for i in 0..FEATURES {
+                        xty[i] += x[i] * sample.tricks_won as f64;
+                        for j in 0..FEATURES {
+                            xtx[i][j] += x[i] * x[j];
+                        }
+                    }
+                }
+                for (i, row) in xtx.iter_mut().enumerate() {
+                    row[i] += ridge;
+                }
+                let w = solve(xtx, xty);
+                let values = HandSizeValues {
+                    plain: RANKS
+                        .map(|rank| (rank, w[rank as usize - 2] as f32))
+                        .collect(),
+                    trump: RANKS
+                        .map(|rank| (rank, w[rank as usize + 11] as f32))
+                        .collect(),
+                    joker: w[26] as f32,
+                };
+                (size, values)
+            })
+            .collect();
+        CardValues { hand_sizes }
+    }
+}
+
+/// Every hand played in a finished game. Hands are rebuilt from the tricks,
+/// since every card dealt gets played.
+pub fn hand_samples(game: &GameState) -> Vec<HandSample> {
+    let mut samples = vec![];
+    for summary in game.round_summaries.iter() {
+        let tricks = match game
+            .round_history
+            .iter()
+            .find(|record| record.round == summary.round)
+        {
+            Some(record) => &record.tricks,
+            // only a game ended early by a forfeit leaves its last round out
+            // of the history
+            None => &game.tricks,
+        };
+        for result in summary.results.iter() {
+            let hand: Vec<Card> = tricks
+                .iter()
+                .flat_map(|trick| trick.cards.iter())
+                .filter(|card| card.played_by.as_ref() == Some(&result.player))
+                .cloned()
+                .collect();
+            if hand.len() as i32 != summary.cards_dealt {
+                continue;
+            }
+            samples.push(HandSample {
+                hand,
+                trump: summary.trump.clone(),
+                tricks_won: result.tricks_won,
+            });
+        }
+    }
+    samples
+}
+
+/// Bids the tricks its `CardValues` expect the hand to take, and plays like
+/// `TrickAware`.
+pub struct Learned {
+    pub values: CardValues,
+}
+
+impl Strategy for Learned {
+    fn bid(&self, obs: &PlayerObservation) -> i32 {
+        closest_valid_bid(obs, self.values.expected_tricks(&obs.hand, &obs.trump))
+    }
+
+    fn play(&self, obs: &PlayerObservation) -> Card {
+        TrickAware.play(obs)
+    }
+}
+
+fn feature(card: &Card, trump: &Suit) -> usize {
+    if card.suit == Suit::Joker {
+        return 26;
+    }
+    let rank = card.value.clamp(2, 14) as usize - 2;
+    if effective_suit(card, trump) == *trump {
+        13 + rank
+    } else {
+        rank
+    }
+}
+
+/// Solves `a x = b` by Gaussian elimination with partial pivoting.
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Vec<f64> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))
+            .unwrap();
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        if a[col][col].abs() < 1e-12 {
+            continue;
+        }
+        let pivot_row = a[col].clone();
+        for row in col + 1..n {
+            let factor = a[row][col] / pivot_row[col];
+            for (value, pivot_value) in a[row].iter_mut().zip(pivot_row.iter()).skip(col) {
+                *value -= factor * pivot_value;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        if a[row][row].abs() < 1e-12 {
+            continue;
+        }
+        let rest: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - rest) / a[row][row];
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{create_deck, SetupGameOptions};
+
+    use super::super::play_game;
+    use super::*;
+
+    #[test]
+    fn test_fit_finds_the_cards_that_take_tricks() {
+        fastrand::seed(2);
+        let trump = Suit::Heart;
+        let samples: Vec<HandSample> = (0..3000)
+            .map(|_| {
+                let mut deck = create_deck();
+                fastrand::shuffle(&mut deck);
+                let hand = deck[..5].to_vec();
+                // every ace and every trump king takes a trick
+                let tricks_won = hand
+                    .iter()
+                    .filter(|card| card.value == 14 || (card.suit == trump && card.value == 13))
+                    .count() as i32;
+                HandSample {
+                    hand,
+                    trump: trump.clone(),
+                    tricks_won,
+                }
+            })
+            .collect();
+
+        let values = CardValues::fit(&samples, 0.1);
+        let five = &values.hand_sizes[&5];
+        assert!((five.plain[&14] - 1.0).abs() < 0.05);
+        assert!((five.trump[&14] - 1.0).abs() < 0.05);
+        assert!((five.trump[&13] - 1.0).abs() < 0.05);
+        assert!(five.plain[&13].abs() < 0.05);
+        assert!(five.plain[&2].abs() < 0.05);
+        // nothing was learned about jokers
+        assert_eq!(five.joker, 0.0);
+    }
+
+    #[test]
+    fn test_hand_samples_cover_every_hand() {
+        fastrand::seed(4);
+        let strategies: Vec<Box<dyn Strategy + Send + Sync>> = (0..3)
+            .map(|_| -> Box<dyn Strategy + Send + Sync> { Box::new(TrickAware) })
+            .collect();
+        let game = play_game(
+            &strategies,
+            SetupGameOptions {
+                rounds: 3,
+                ..SetupGameOptions::new()
+            },
+        );
+        let samples = hand_samples(&game);
+        assert_eq!(samples.len(), 3 * game.round_summaries.len());
+
+        let cards_dealt: i32 = game.round_summaries.iter().map(|s| s.cards_dealt).sum();
+        let tricks_won: i32 = samples.iter().map(|s| s.tricks_won).sum();
+        assert_eq!(tricks_won, cards_dealt);
+        let cards_held: usize = samples.iter().map(|s| s.hand.len()).sum();
+        assert_eq!(cards_held as i32, 3 * cards_dealt);
+    }
+
+    #[test]
+    fn test_values_round_trip_through_a_file() {
+        // test runs side by side each get their own file
+        let path = std::env::temp_dir().join(format!(
+            "blackball_card_values_test_{}_{}.json",
+            std::process::id(),
+            nanoid::nanoid_gen(8)
+        ));
+        let values = CardValues::default();
+        values.save(&path).unwrap();
+        assert_eq!(CardValues::load(&path).unwrap(), values);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -18,6 +18,7 @@ impl GameClient {
             role,
             // encrypted_hand: vec![],
             encrypted_hand: String::new(),
+            encrypted_legal_moves: String::new(),
             num_cards: 0,
             details: PlayerDetails {
                 username: id.clone(),
@@ -25,6 +26,8 @@ impl GameClient {
                 client_secret: Some(secret),
                 lobby: lobby,
             },
+            ready: false,
+            seat: None,
         }
     }
 
@@ -5,7 +5,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::{collections::HashMap, fmt};
 
-mod ai;
+pub mod ai;
 mod client;
 mod game;
 
@@ -65,6 +65,7 @@ pub enum PlayerRole {
 pub enum GameError {
     InternalIssue(String),
     NotEnoughPlayers,
+    NotEnoughCards,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -75,9 +76,19 @@ pub struct GameClient {
     // #[serde(skip)]
     // pub secret: String,
     pub encrypted_hand: String,
+    /// The bids or cards this player can choose from, only set for the player
+    /// on turn and encrypted like `encrypted_hand`. See
+    /// `GameState::decrypt_legal_moves`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub encrypted_legal_moves: String,
     pub num_cards: i32,
     pub role: PlayerRole,
     pub details: PlayerDetails,
+    /// Set by the player in the lobby, reset when a game starts.
+    pub ready: bool,
+    /// 1-based seat at the table. Claimed in the lobby, assigned to everyone
+    /// once the game starts.
+    pub seat: Option<usize>,
 }
 pub fn xor_encrypt_decrypt(data: &str, key: &str) -> Vec<u8> {
     data.as_bytes()
@@ -134,19 +145,47 @@ pub struct GameState {
     #[serde(skip)]
     curr_player_turn_idx: usize,
     pub curr_winning_card: Option<Card>,
+    pub tricks: Vec<TrickRecord>,
+    pub round_history: Vec<RoundRecord>,
     pub curr_dealer: String,
     #[serde(skip)]
     curr_dealer_idx: usize,
     pub bids: HashMap<String, Option<i32>>,
     pub player_bids: Vec<(String, i32)>,
+    pub round_lead: Option<RoundLead>,
+    /// Cards drawn to pick the first dealer with `FirstDealer::CutForDeal`.
+    pub dealer_cut: Vec<Card>,
     pub wins: HashMap<String, i32>,
     pub score: HashMap<String, i32>,
+    pub round_summary: Option<RoundSummary>,
+    pub round_summaries: Vec<RoundSummary>,
+    pub game_result: Option<GameResult>,
+    pub rematch: Option<RematchRequest>,
+    pub series: Vec<GameResult>,
+    /// Players who left mid-game under `LeavePolicy::Forfeit`, in leaving order.
+    pub forfeited: Vec<String>,
     pub gameplay_state: GameplayState,
+    /// Set by the leader to take a break. Player actions are rejected while set.
+    pub paused: bool,
+    pub paused_at: Option<DateTime<Utc>>,
+    /// Players who acknowledged the finished trick or round.
+    pub acks: Vec<String>,
+    /// When `PostHand`/`PostRound` moves on without the missing acks.
+    pub ack_deadline: Option<DateTime<Utc>>,
+    /// Difficulty of each computer at the table.
+    pub computer_difficulty: HashMap<String, Difficulty>,
+    /// When the computer whose turn it is makes its move.
+    pub computer_move_at: Option<DateTime<Utc>>,
+    /// Who asked for a hint, answered from `tick`.
+    #[serde(skip)]
+    hint_requested: Option<String>,
+    pub undo_request: Option<UndoRequest>,
     pub event_log: Vec<GameMessage>,
     pub system_status: Vec<String>, // useful to tell players what is going wrong
     is_public: bool,
     pub updated_at: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
+    pub started_at: DateTime<Utc>,
     pub trump_played_in_round: bool,
 }
 
@@ -157,6 +196,9 @@ impl GameState {
     pub fn get_max_players(&self) -> usize {
         return self.setup_game_options.max_players;
     }
+    pub fn get_setup_game_options(&self) -> &SetupGameOptions {
+        &self.setup_game_options
+    }
 
     pub fn decrypt_player_hand(hand: String, player_secret: &String) -> Vec<Card> {
         // info!("Decrypting hand: {:?}, {:?}", hand, player_secret);
@@ -178,6 +220,16 @@ impl GameState {
             serde_json::from_slice(&secret_data).expect("Could not parse hand");
         actual_hand
     }
+
+    /// The player's `encrypted_legal_moves`, `None` when it isn't their turn.
+    pub fn decrypt_legal_moves(encrypted: &str, player_secret: &str) -> Option<LegalMoves> {
+        if encrypted.is_empty() || player_secret.is_empty() {
+            return None;
+        }
+        let encoded = BASE64.decode(encrypted.as_bytes()).ok()?;
+        let plaintext = String::from_utf8(encoded).ok()?;
+        serde_json::from_slice(&xor_encrypt_decrypt(&plaintext, player_secret)).ok()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -186,11 +238,54 @@ pub struct GameEventResult {
     pub msg: GameActionResponse,
 }
 
+/// Everything a computer needs to pick its move, taken from the table so the
+/// search can run without holding on to the game.
+pub struct ComputerTurn {
+    pub username: String,
+    strategy: Box<dyn ai::Strategy + Send + Sync>,
+    observation: ai::PlayerObservation,
+    /// Where the game was when the turn was handed out. A move for a table
+    /// that has moved on since is thrown away, messages that leave the table
+    /// as it was (`CurrentState`, hints, rejected moves) don't matter.
+    progress: TableProgress,
+}
+
+/// A hint someone on turn asked for, taken from the table like a
+/// `ComputerTurn` so working it out doesn't hold on to the game.
+pub struct HintRequest {
+    pub username: String,
+    observation: ai::PlayerObservation,
+    budget: SearchBudget,
+    progress: TableProgress,
+}
+
+/// Round, state, player on turn, bids made, cards in the trick and seats.
+type TableProgress = (i32, GameplayState, Option<String>, usize, usize, usize);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum GameActionResponse {
     Connect(Connect),
     GameState(GameState),
     Message(String),
+    /// Answer to `GameAction::RequestHint`, sent only to whoever asked.
+    Hint(Hint),
+}
+
+/// The moves open to the player on turn, worked out by the engine so clients
+/// don't have to know the rules.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LegalMoves {
+    Bids(Vec<i32>),
+    Cards(Vec<Card>),
+}
+
+/// A suggested move and why, see `ai::hint`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hint {
+    /// A `GameAction::Bid` or `GameAction::PlayCard`.
+    pub action: GameAction,
+    pub explanation: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -203,6 +298,28 @@ pub struct SetupGameOptions {
     pub visibility: GameVisibility,
     pub password: Option<String>,
     pub computer_players: usize,
+    pub first_lead: FirstLeadPolicy,
+    pub decks: usize,
+    pub jokers: usize,
+    pub identical_card_rule: IdenticalCardRule,
+    pub on_leave: LeavePolicy,
+    /// Ignore claimed seats and seat everyone at random.
+    pub random_seats: bool,
+    pub first_dealer: FirstDealer,
+    /// Seconds to wait for everyone to acknowledge a finished trick or round
+    /// before moving on anyway. `None` waits for everyone.
+    pub auto_advance_seconds: Option<u64>,
+    /// How long computers think before each bid or card, capped at
+    /// `MAX_COMPUTER_THINK_MS`.
+    pub computer_think_ms: u64,
+    /// Difficulty of each computer player, `cpu_0` first. Computers without an
+    /// entry, and computers playing for someone who left, are `Medium`.
+    pub computer_difficulty: Vec<Difficulty>,
+    /// How long `Expert` computers search, `time_ms` capped at
+    /// `MAX_SEARCH_MS`.
+    pub search_budget: SearchBudget,
+    /// Players can ask for a suggested bid or card on their turn.
+    pub hints: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -211,6 +328,111 @@ pub enum GameVisibility {
     Private,
 }
 
+/// Who plays the first card of a round once bidding is over.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum FirstLeadPolicy {
+    /// Highest bid leads, ties go to the tied player closest to the dealer's left.
+    HighestBidder,
+    /// The player to the dealer's left leads, regardless of bids.
+    LeftOfDealer,
+}
+
+/// How a trick is settled when two identical cards are played, which can only
+/// happen when playing with more than one deck (or more than one joker).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum IdenticalCardRule {
+    LaterPlayedWins,
+    EarlierPlayedWins,
+}
+
+/// Who deals the first round of a game.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum FirstDealer {
+    Random,
+    /// Whoever sits in seat 1.
+    SeatOne,
+    /// Everyone draws a card, highest card deals. Ties draw again.
+    CutForDeal,
+}
+
+/// How well a computer player plays, see `ai::strategy_for`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum Difficulty {
+    /// Any valid bid, any legal card.
+    Easy,
+    /// Bids on hand strength.
+    #[default]
+    Medium,
+    /// Bids the tricks it takes in simulated rounds and plays to make its bid.
+    Hard,
+    /// Plays out many possible deals of the cards it can't see, within the
+    /// table's `SearchBudget`.
+    Expert,
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Easy => "easy",
+            Self::Medium => "medium",
+            Self::Hard => "hard",
+            Self::Expert => "expert",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "easy" => Ok(Self::Easy),
+            "medium" => Ok(Self::Medium),
+            "hard" => Ok(Self::Hard),
+            "expert" => Ok(Self::Expert),
+            _ => Err(format!(
+                "Unknown difficulty {s}, expected easy, medium, hard or expert"
+            )),
+        }
+    }
+}
+
+/// How much the `Expert` computer may search before each bid or card.
+/// Whichever limit is hit first ends the search.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SearchBudget {
+    /// Simulated rounds.
+    pub iterations: usize,
+    pub time_ms: u64,
+}
+
+impl Default for SearchBudget {
+    fn default() -> Self {
+        SearchBudget {
+            iterations: 2000,
+            time_ms: 250,
+        }
+    }
+}
+
+/// What happens to a seat when its player leaves a game that has started.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LeavePolicy {
+    /// A computer keeps playing the seat, with the player's hand and score.
+    ReplaceWithComputer,
+    /// The seat is removed and the player is ranked last in the results.
+    Forfeit,
+}
+
+/// Who leads the current round and why, so clients can explain it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RoundLead {
+    pub player: String,
+    pub policy: FirstLeadPolicy,
+    pub reason: String,
+}
+
 impl Default for SetupGameOptions {
     fn default() -> Self {
         Self::new()
@@ -228,6 +450,18 @@ impl SetupGameOptions {
             visibility: GameVisibility::Public,
             password: None,
             computer_players: 0,
+            first_lead: FirstLeadPolicy::HighestBidder,
+            decks: 1,
+            jokers: 0,
+            identical_card_rule: IdenticalCardRule::LaterPlayedWins,
+            on_leave: LeavePolicy::ReplaceWithComputer,
+            random_seats: false,
+            first_dealer: FirstDealer::SeatOne,
+            auto_advance_seconds: Some(10),
+            computer_think_ms: 1000,
+            computer_difficulty: vec![],
+            search_budget: SearchBudget::default(),
+            hints: true,
         }
     }
 
@@ -249,10 +483,27 @@ impl SetupGameOptions {
             visibility,
             password,
             computer_players: 0,
+            first_lead: FirstLeadPolicy::HighestBidder,
+            decks: 1,
+            jokers: 0,
+            identical_card_rule: IdenticalCardRule::LaterPlayedWins,
+            on_leave: LeavePolicy::ReplaceWithComputer,
+            random_seats: false,
+            first_dealer: FirstDealer::SeatOne,
+            auto_advance_seconds: Some(10),
+            computer_think_ms: 1000,
+            computer_difficulty: vec![],
+            search_budget: SearchBudget::default(),
+            hints: true,
         }
     }
 }
 
+pub const CARDS_PER_DECK: usize = 52;
+pub const JOKER_VALUE: i32 = 15;
+pub const MAX_COMPUTER_THINK_MS: u64 = 5000;
+pub const MAX_SEARCH_MS: u64 = 2000;
+
 pub fn create_deck() -> Vec<Card> {
     let mut cards = vec![];
 
@@ -290,6 +541,30 @@ pub fn create_deck() -> Vec<Card> {
     cards
 }
 
+/// Builds a shoe of `decks` standard decks plus `jokers` jokers. Every physical
+/// card gets its own id: deck `n` uses ids `n * 52..(n + 1) * 52` following the
+/// single deck layout, and jokers take the ids after the last deck.
+pub fn create_decks(decks: usize, jokers: usize) -> Vec<Card> {
+    let mut cards = vec![];
+    for deck in 0..decks {
+        cards.extend(create_deck().into_iter().map(|mut card| {
+            card.id += deck * CARDS_PER_DECK;
+            card
+        }));
+    }
+
+    for joker in 0..jokers {
+        cards.push(Card {
+            id: decks * CARDS_PER_DECK + joker,
+            suit: Suit::Joker,
+            value: JOKER_VALUE,
+            played_by: None,
+        });
+    }
+
+    cards
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Connect {
     pub username: String,
@@ -317,12 +592,27 @@ pub enum GameAction {
     Bid(i32),
     Ack,
 
+    Ready(bool),
+    ClaimSeat(usize),
+
     // System actions
     StartGame(SetupGameOptions),
+    /// Leader only: start even if some players aren't ready.
+    ForceStart(SetupGameOptions),
     Deal,
     CurrentState,
     Connect(PlayerDetails),
     JoinGame(PlayerDetails),
+    Rematch(RematchOptions),
+    AcceptRematch,
+    Leave,
+    Pause,
+    Resume,
+    RequestUndo,
+    ApproveUndo,
+    DeclineUndo,
+    /// Ask the computer what to bid or play, see `SetupGameOptions::hints`.
+    RequestHint,
 }
 
 #[derive(Debug, Clone, PartialOrd, PartialEq, Ord, Eq, Serialize, Deserialize)]
@@ -341,6 +631,7 @@ pub enum Suit {
     Club,
     Spade,
     NoTrump,
+    Joker,
 }
 
 impl fmt::Display for Card {
@@ -362,6 +653,7 @@ impl fmt::Display for Suit {
             &Self::Club => "C",
             &Self::Spade => "S",
             &Self::NoTrump => "None",
+            &Self::Joker => "Joker",
         };
         write!(f, "{}", suit)
     }
@@ -395,6 +687,120 @@ impl Card {
     }
 }
 
+/// A finished trick (hand): who led, every card in the order it was played,
+/// and which card took it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TrickRecord {
+    pub leader: String,
+    pub cards: Vec<Card>,
+    pub winner: String,
+    pub winning_card: Card,
+}
+
+/// All tricks of a finished round, kept so clients can review past rounds.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RoundRecord {
+    pub round: i32,
+    pub trump: Suit,
+    pub dealer: String,
+    pub tricks: Vec<TrickRecord>,
+}
+
+/// Scoresheet line for one player in one round.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PlayerRoundResult {
+    pub player: String,
+    pub bid: i32,
+    pub tricks_won: i32,
+    pub points: i32,
+    pub made_bid: bool,
+    pub total_score: i32,
+}
+
+/// Results of a finished round, computed when the round enters `PostRound`.
+/// Players are listed in seating order.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RoundSummary {
+    pub round: i32,
+    pub trump: Suit,
+    pub cards_dealt: i32,
+    pub results: Vec<PlayerRoundResult>,
+}
+
+/// Whole-game stats for one player, built from the round summaries.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PlayerGameStats {
+    pub rounds_played: i32,
+    pub bids_made: i32,
+    pub blackballs: i32,
+    pub tricks_won: i32,
+    pub best_round: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Standing {
+    /// 1 is first place. Players that are still tied after every tie-break
+    /// share a rank, and the next rank is skipped (1, 1, 3).
+    pub rank: usize,
+    pub player: String,
+    pub score: i32,
+    pub stats: PlayerGameStats,
+    pub forfeited: bool,
+}
+
+/// Final results of a finished game, set when the game enters `End`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GameResult {
+    pub game_id: String,
+    pub lobby_code: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub rounds_played: i32,
+    pub standings: Vec<Standing>,
+    /// The game this one was a rematch of, if any.
+    pub previous_game_id: Option<String>,
+}
+
+/// How the table is seated when a rematch starts.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RematchSeating {
+    /// Same seats, same first dealer.
+    Keep,
+    /// Same seats, the first dealer moves one seat to the left.
+    RotateDealer,
+    /// Everyone is reseated at random.
+    Shuffle,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RematchOptions {
+    pub seating: RematchSeating,
+}
+
+/// The bid or card an undo would take back.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum UndoTarget {
+    Bid { player: String, bid: i32 },
+    Card { player: String, card: Card },
+}
+
+/// A player asking to take back their last bid or card. Everyone else at the
+/// table has to approve before anything else is played.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UndoRequest {
+    pub requested_by: String,
+    pub target: UndoTarget,
+    pub approved: Vec<String>,
+}
+
+/// A rematch proposed by the leader, waiting on the other players.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RematchRequest {
+    pub requested_by: String,
+    pub options: RematchOptions,
+    pub confirmed: Vec<String>,
+}
+
 #[derive(Deserialize, Debug, Serialize, Clone)]
 pub struct GameMessage {
     pub username: String,